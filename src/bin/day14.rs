@@ -1,116 +1,169 @@
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
-    iter,
+    cmp::Reverse,
+    collections::{HashSet, VecDeque},
+    ops::Range,
 };
 
-use glam::{I8Vec2, I16Vec2, U8Vec2};
+use glam::IVec2;
 use nom::{
     bytes::complete::tag,
-    character::complete::{i16, line_ending, space1, u8},
+    character::complete::{i32, line_ending, space1},
     multi::many1,
     sequence::{separated_pair, terminated},
 };
 
-fn parse_position(input: &str) -> nom::IResult<&str, U8Vec2> {
-    let (input, _) = tag("p=")(input)?;
-    let (input, (x, y)) = separated_pair(u8, tag(","), u8)(input)?;
-    Ok((input, U8Vec2::new(x, y)))
-}
-
-fn parse_velocity(input: &str) -> nom::IResult<&str, I16Vec2> {
-    let (input, _) = tag("v=")(input)?;
-    let (input, (x, y)) = separated_pair(i16, tag(","), i16)(input)?;
-    Ok((input, I16Vec2::new(x, y)))
+fn parse_vec(prefix: &'static str) -> impl FnMut(&str) -> nom::IResult<&str, IVec2> {
+    move |input| {
+        let (input, _) = tag(prefix)(input)?;
+        let (input, (x, y)) = separated_pair(i32, tag(","), i32)(input)?;
+        Ok((input, IVec2::new(x, y)))
+    }
 }
 
-fn parse(input: &str) -> nom::IResult<&str, Vec<(U8Vec2, I16Vec2)>> {
+fn parse(input: &str) -> nom::IResult<&str, Vec<(IVec2, IVec2)>> {
     many1(terminated(
-        separated_pair(parse_position, space1, parse_velocity),
+        separated_pair(parse_vec("p="), space1, parse_vec("v=")),
         line_ending,
     ))(input)
 }
 
-const TILES_WIDTH: u8 = 101;
-const MID_WIDTH: u8 = 50;
-const TILES_HEIGHT: u8 = 103;
-const MID_HEIGHT: u8 = 51;
-const TSECS: u8 = 100;
-
-fn part1(parsed: &[(U8Vec2, I16Vec2)]) {
-    let quad = parsed.iter().fold([0u32; 4], |mut acc, robot| {
-        // let endx = ((((robot.0.x as i16 + robot.1.x * TSECS as i16) % TILES_WIDTH as i16)
-        //     + TILES_WIDTH as i16)
-        //     % TILES_WIDTH as i16) as u8;
-        // let endy = ((((robot.0.y as i16 + robot.1.y * TSECS as i16) % TILES_HEIGHT as i16)
-        //     + TILES_HEIGHT as i16)
-        //     % TILES_HEIGHT as i16) as u8;
-
-        let grid_size = U8Vec2::new(TILES_WIDTH, TILES_HEIGHT).as_i16vec2();
-        let endp = ((((robot.0.as_i16vec2() + robot.1 * TSECS as i16) % grid_size) + grid_size)
-            % grid_size)
-            .as_u8vec2();
-
-        if (MID_WIDTH + 1..TILES_WIDTH).contains(&endp.x) && (0..MID_HEIGHT).contains(&endp.y) {
-            acc[1] += 1;
-        } else if (0..MID_WIDTH).contains(&endp.x) && (0..MID_HEIGHT).contains(&endp.y) {
-            acc[0] += 1;
-        } else if (0..MID_WIDTH).contains(&endp.x)
-            && (MID_HEIGHT + 1..TILES_HEIGHT).contains(&endp.y)
-        {
-            acc[3] += 1;
-        } else if (MID_WIDTH + 1..TILES_WIDTH).contains(&endp.x)
-            && (MID_HEIGHT + 1..TILES_HEIGHT).contains(&endp.y)
-        {
-            acc[2] += 1;
-        }
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Region {
+    x: Range<i32>,
+    y: Range<i32>,
+}
 
-        // println!("pos={:?}", robot.0);
-        acc
-    });
+impl Region {
+    fn contains(&self, pos: IVec2) -> bool {
+        self.x.contains(&pos.x) && self.y.contains(&pos.y)
+    }
+}
 
-    println!("{:?}", quad.into_iter().reduce(|acc, q| acc * q));
+#[derive(Debug, Clone)]
+struct Swarm {
+    size: IVec2,
+    robots: Vec<(IVec2, IVec2)>,
+    time: i64,
 }
 
-fn part2_render(parsed: &[(U8Vec2, I16Vec2)]) {
-    let mut m = HashMap::new();
-    for t in 0..TSECS + 1 {
-        for robot in parsed.iter() {
-            // let px = ((((robot.0.x as i16 + robot.1.x * t as i16) % TILES_WIDTH as i16)
-            //     + TILES_WIDTH as i16)
-            //     % TILES_WIDTH as i16) as u8;
-            // let py = ((((robot.0.y as i16 + robot.1.y * t as i16) % TILES_HEIGHT as i16)
-            //     + TILES_HEIGHT as i16)
-            //     % TILES_HEIGHT as i16) as u8;
+impl Swarm {
+    fn new(robots: Vec<(IVec2, IVec2)>, size: IVec2) -> Self {
+        assert!(size.min_element() > 0, "grid must not be empty");
+        Self {
+            size,
+            robots,
+            time: 0,
+        }
+    }
+
+    fn time(&self) -> i64 {
+        self.time
+    }
 
-            let grid_size = U8Vec2::new(TILES_WIDTH, TILES_HEIGHT).as_i16vec2();
-            let np = ((((robot.0.as_i16vec2() + robot.1 * t as i16) % grid_size) + grid_size)
-                % grid_size)
-                .as_u8vec2();
+    fn step(&mut self, secs: i64) {
+        self.time += secs;
+    }
 
-            m.insert(np, '#');
-        }
+    fn set_time(&mut self, time: i64) {
+        self.time = time;
+    }
 
-        println!(
-            "_____________________________________________ t={}s __________________________________________________",
-            t
+    // every robot returns to its start after `period` seconds, so only the
+    // remainder matters; this keeps `v * t` small even for huge times
+    fn period(&self) -> i64 {
+        let (w, h) = (self.size.x as i64, self.size.y as i64);
+        w * h / gcd(w, h)
+    }
+
+    fn position_of(&self, robot: &(IVec2, IVec2), time: i64) -> IVec2 {
+        let t = IVec2::new(
+            time.rem_euclid(self.size.x as i64) as i32,
+            time.rem_euclid(self.size.y as i64) as i32,
         );
-        for y in 0..TILES_HEIGHT {
-            for x in 0..TILES_WIDTH {
-                print!("{}", m.get(&U8Vec2::new(x, y)).unwrap_or(&'.'));
+        (robot.0 + robot.1 * t).rem_euclid(self.size)
+    }
+
+    fn positions_at(&self, time: i64) -> impl Iterator<Item = IVec2> + Clone + '_ {
+        self.robots
+            .iter()
+            .map(move |robot| self.position_of(robot, time))
+    }
+
+    fn positions(&self) -> impl Iterator<Item = IVec2> + Clone + '_ {
+        self.positions_at(self.time)
+    }
+
+    fn count_regions(&self, regions: &[Region]) -> Vec<u32> {
+        self.positions()
+            .fold(vec![0; regions.len()], |mut acc, pos| {
+                for (i, region) in regions.iter().enumerate() {
+                    if region.contains(pos) {
+                        acc[i] += 1;
+                    }
+                }
+                acc
+            })
+    }
+
+    // quadrants exclude the middle row/column when the dimension is odd
+    fn quadrants(&self) -> [Region; 4] {
+        let (w, h) = (self.size.x, self.size.y);
+        let left = 0..w / 2;
+        let right = (w + 1) / 2..w;
+        let top = 0..h / 2;
+        let bottom = (h + 1) / 2..h;
+        [
+            Region {
+                x: left.clone(),
+                y: top.clone(),
+            },
+            Region {
+                x: right.clone(),
+                y: top,
+            },
+            Region {
+                x: right,
+                y: bottom.clone(),
+            },
+            Region { x: left, y: bottom },
+        ]
+    }
+
+    fn safety_factor(&self) -> u64 {
+        self.count_regions(&self.quadrants())
+            .into_iter()
+            .map(u64::from)
+            .product()
+    }
+
+    fn render(&self) -> String {
+        let robot_pos_set = self.positions().collect::<HashSet<_>>();
+        let mut frame = String::new();
+        for y in 0..self.size.y {
+            for x in 0..self.size.x {
+                frame.push(if robot_pos_set.contains(&IVec2::new(x, y)) {
+                    '#'
+                } else {
+                    '.'
+                });
             }
-            println!();
+            frame.push('\n');
         }
-
-        for _ in m.drain() {}
-        println!()
+        frame
     }
 }
 
-fn get_connected_iter(
-    pos: &U8Vec2,
-    robot_pos_set: &HashSet<U8Vec2>,
-    regions: &mut HashSet<U8Vec2>,
-) {
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn part1(swarm: &Swarm) {
+    let mut swarm = swarm.clone();
+    swarm.step(100);
+    println!("part1: {}", swarm.safety_factor());
+}
+
+fn get_connected_iter(pos: &IVec2, robot_pos_set: &HashSet<IVec2>, regions: &mut HashSet<IVec2>) {
     let mut queue = VecDeque::new();
     queue.push_back(*pos);
 
@@ -122,23 +175,18 @@ fn get_connected_iter(
         regions.insert(pos);
 
         for next_pos in [
-            I16Vec2::X,
-            I16Vec2::X + I16Vec2::NEG_Y,
-            I16Vec2::NEG_Y,
-            I16Vec2::NEG_Y + I16Vec2::NEG_X,
-            I16Vec2::NEG_X,
-            I16Vec2::NEG_X + I16Vec2::Y,
-            I16Vec2::Y,
-            I16Vec2::Y + I16Vec2::X,
+            IVec2::X,
+            IVec2::X + IVec2::NEG_Y,
+            IVec2::NEG_Y,
+            IVec2::NEG_Y + IVec2::NEG_X,
+            IVec2::NEG_X,
+            IVec2::NEG_X + IVec2::Y,
+            IVec2::Y,
+            IVec2::Y + IVec2::X,
         ]
         .into_iter()
         .filter_map(|dir| {
-            let next_pos = pos.as_i16vec2() + dir;
-            if next_pos.is_negative_bitmask() > 0 {
-                return None;
-            }
-            let next_pos = next_pos.as_u8vec2();
-
+            let next_pos = pos + dir;
             robot_pos_set.contains(&next_pos).then_some(next_pos)
         }) {
             queue.push_back(next_pos);
@@ -146,38 +194,143 @@ fn get_connected_iter(
     }
 }
 
-fn part2_heu(parsed: &[(U8Vec2, I16Vec2)]) {
+fn largest_cluster(robot_pos_set: &HashSet<IVec2>) -> usize {
     let mut connected = HashSet::new();
     let mut visited = HashSet::new();
-    let grid_size = U8Vec2::new(TILES_WIDTH, TILES_HEIGHT).as_i16vec2();
+    let mut largest = 0;
 
-    for t in 0..TSECS + 1 {
-        let robot_pos_set = parsed
-            .iter()
-            .map(|robot| {
-                ((((robot.0.as_i16vec2() + robot.1 * t as i16) % grid_size) + grid_size)
-                    % grid_size)
-                    .as_u8vec2()
-            })
-            .collect::<HashSet<_>>();
+    for pos in robot_pos_set.iter() {
+        if visited.contains(pos) {
+            continue;
+        }
 
-        for pos in robot_pos_set.iter() {
-            if visited.contains(pos) {
-                continue;
-            }
+        get_connected_iter(pos, robot_pos_set, &mut connected);
+        largest = largest.max(connected.len());
+
+        visited.extend(connected.drain());
+    }
+
+    largest
+}
 
-            get_connected_iter(pos, &robot_pos_set, &mut connected);
+fn part2_heu(swarm: &Swarm) -> Option<(i64, usize)> {
+    (0..swarm.period())
+        .map(|t| {
+            let robot_pos_set = swarm.positions_at(t).collect::<HashSet<_>>();
+            (t, largest_cluster(&robot_pos_set))
+        })
+        .max_by_key(|&(t, size)| (size, Reverse(t)))
+}
+
+fn variance(values: impl Iterator<Item = i32> + Clone) -> f64 {
+    let (n, sum) = values
+        .clone()
+        .fold((0f64, 0f64), |(n, sum), v| (n + 1.0, sum + v as f64));
+    let mean = sum / n;
+    values.map(|v| (v as f64 - mean).powi(2)).sum::<f64>() / n
+}
+
+fn min_variance_time(swarm: &Swarm, period: i32, axis: fn(IVec2) -> i32) -> i64 {
+    (0..period as i64)
+        .map(|t| (t, variance(swarm.positions_at(t).map(axis))))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(t, _)| t)
+        .unwrap()
+}
 
-            visited.extend(connected.drain());
+// t = tx (mod w), t = ty (mod h)
+fn crt(tx: i64, w: i64, ty: i64, h: i64) -> Option<i64> {
+    (0..h / gcd(w, h)).map(|k| tx + k * w).find(|t| t % h == ty)
+}
+
+fn part2(swarm: &Swarm) -> Option<(i64, String)> {
+    let tx = min_variance_time(swarm, swarm.size.x, |p| p.x);
+    let ty = min_variance_time(swarm, swarm.size.y, |p| p.y);
+    let candidate = crt(tx, swarm.size.x as i64, ty, swarm.size.y as i64);
+
+    // the tree frame has a cluster far larger than anything random noise produces;
+    // fall back to scanning every second when the variance minima disagree
+    let is_tree = |size: usize| size * 10 >= swarm.robots.len();
+    let t = match candidate.filter(|&t| {
+        is_tree(largest_cluster(
+            &swarm.positions_at(t).collect::<HashSet<_>>(),
+        ))
+    }) {
+        Some(t) => t,
+        None => {
+            let (t, size) = part2_heu(swarm)?;
+            if !is_tree(size) {
+                return None;
+            }
+            t
         }
-    }
+    };
+
+    let mut swarm = swarm.clone();
+    swarm.set_time(t);
+    Some((swarm.time(), swarm.render()))
 }
 
 fn main() -> anyhow::Result<()> {
+    let input = include_str!("../../inputs/day14.sample");
+    let (_, parsed) = parse(input)?;
+    let sample = Swarm::new(parsed, IVec2::new(11, 7));
+    part1(&sample);
+
     let input = include_str!("../../inputs/day14.input");
     let (_, parsed) = parse(input)?;
-    // println!("{:?}", parsed);
-    part1(&parsed);
-    // part2_render(&parsed);
+    let swarm = Swarm::new(parsed, IVec2::new(101, 103));
+    part1(&swarm);
+    match part2(&swarm) {
+        Some((t, frame)) => println!("part2: {}\n{}", t, frame),
+        None => println!("part2: no easter egg found"),
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Swarm {
+        let (_, parsed) = parse(include_str!("../../inputs/day14.sample")).unwrap();
+        Swarm::new(parsed, IVec2::new(11, 7))
+    }
+
+    #[test]
+    fn sample_safety_factor() {
+        let mut swarm = sample();
+        swarm.step(100);
+        assert_eq!(swarm.safety_factor(), 12);
+    }
+
+    #[test]
+    fn stepping_back_undoes_stepping_forward() {
+        let mut swarm = sample();
+        let start = swarm.positions().collect::<Vec<_>>();
+
+        swarm.step(1234);
+        swarm.step(-1234);
+        assert_eq!(swarm.time(), 0);
+        assert_eq!(swarm.positions().collect::<Vec<_>>(), start);
+
+        swarm.step(-1);
+        assert_eq!(
+            swarm.positions().collect::<Vec<_>>(),
+            swarm.positions_at(swarm.period() - 1).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn counts_custom_regions() {
+        // three horizontal bands split on the middle row, plus an overlapping
+        // left half
+        let regions = [
+            Region { x: 0..11, y: 0..3 },
+            Region { x: 0..11, y: 3..4 },
+            Region { x: 0..11, y: 4..7 },
+            Region { x: 0..5, y: 0..7 },
+        ];
+        assert_eq!(sample().count_regions(&regions), [4, 4, 4, 6]);
+    }
+}
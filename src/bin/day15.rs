@@ -1,11 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use glam::{I16Vec2, U8Vec2};
 use nom::{
-    bytes::complete::{is_not, take_while},
-    character::complete::{anychar, line_ending},
+    bytes::complete::is_not,
+    character::complete::line_ending,
     combinator::map,
-    multi::{fold_many1, many_till, many1, many1_count},
+    multi::fold_many1,
     sequence::{separated_pair, tuple},
 };
 
@@ -68,155 +68,220 @@ fn parse(input: &str) -> nom::IResult<&str, Parsed> {
     )(input)
 }
 
-fn move_all_in_dir(map: &mut HashMap<U8Vec2, char>, start_pos: U8Vec2, dir: I16Vec2) -> bool {
-    let mut next_pos = start_pos.as_i16vec2() + dir;
-    let mut to_move = 1;
-    loop {
+fn widen(parsed: Parsed) -> Parsed {
+    let map = parsed
+        .map
+        .into_iter()
+        .flat_map(|(pos, c)| {
+            let (l, r) = match c {
+                '#' => ('#', '#'),
+                'O' => ('[', ']'),
+                '@' => ('@', '.'),
+                _ => ('.', '.'),
+            };
+            let pos = U8Vec2::new(pos.x * 2, pos.y);
+            [(pos, l), (pos + U8Vec2::X, r)]
+        })
+        .collect();
+
+    Parsed {
+        map,
+        start_pos: U8Vec2::new(parsed.start_pos.x * 2, parsed.start_pos.y),
+        directions: parsed.directions,
+    }
+}
+
+// pushes everything in front of `start_pos` one step in `dir`; a wide box
+// pushed vertically drags its other half along, so the cells to move form a
+// tree that is collected breadth-first and only moved if no branch hits a wall
+fn push_boxes(map: &mut HashMap<U8Vec2, char>, start_pos: U8Vec2, dir: I16Vec2) -> bool {
+    let mut to_move = vec![start_pos];
+    let mut seen = HashSet::from([start_pos]);
+    let mut i = 0;
+
+    while i < to_move.len() {
+        let next_pos = to_move[i].as_i16vec2() + dir;
+        i += 1;
         if next_pos.is_negative_bitmask() > 0 {
-            break;
+            return false;
         }
+        let next_pos = next_pos.as_u8vec2();
 
-        if map.get(&next_pos.as_u8vec2()).is_some_and(|c| c != &'O') {
-            break;
-        }
+        let mut push = |pos: U8Vec2| {
+            if seen.insert(pos) {
+                to_move.push(pos);
+            }
+        };
 
-        to_move += 1;
-        next_pos += dir;
-    }
-
-    if map.get(&next_pos.as_u8vec2()).is_some_and(|c| c == &'.') {
-        for i in 0..to_move {
-            let pos = (start_pos.as_i16vec2() + dir) + (i * dir);
-            assert!(pos.is_negative_bitmask() == 0, "skill issue");
-            let pos = pos.as_u8vec2();
-            // println!(
-            //     "({:?} + {:?}) + ({:?} * {:?}) = {:?}",
-            //     start_pos.as_i16vec2(),
-            //     dir,
-            //     i,
-            //     dir,
-            //     pos,
-            // );
-            map.entry(pos).and_modify(|c| *c = 'O');
+        match map.get(&next_pos) {
+            Some('.') => {}
+            Some('O') => push(next_pos),
+            Some('[') => {
+                push(next_pos);
+                if dir.y != 0 {
+                    push(next_pos + U8Vec2::X);
+                }
+            }
+            Some(']') => {
+                push(next_pos);
+                if dir.y != 0 {
+                    push(next_pos - U8Vec2::X);
+                }
+            }
+            _ => return false,
         }
+    }
 
-        true
-    } else {
-        false
+    let moved = to_move
+        .iter()
+        .map(|pos| (*pos, map.insert(*pos, '.').unwrap()))
+        .collect::<Vec<_>>();
+    for (pos, c) in moved {
+        map.insert((pos.as_i16vec2() + dir).as_u8vec2(), c);
     }
+
+    true
 }
 
-fn part1(mut parsed: Parsed) {
-    let mut pos = parsed.start_pos;
-    println!("start_pos={:?}", pos);
-    // println!("directions={:?}", parsed.directions);
-    // println!(
-    //     "blocks={:?}",
-    //     parsed
-    //         .map
-    //         .iter()
-    //         .filter(|(k, v)| v == &&b'#')
-    //         .collect::<Vec<_>>()
-    // );
-    //
-    for dir in parsed.directions.into_iter() {
-        // println!("dir={:?}", dir);
-        let next_pos = pos.as_i16vec2() + dir;
-        if next_pos.is_negative_bitmask() > 0 {
-            continue;
+#[derive(Debug, Clone)]
+struct Snapshot {
+    map: HashMap<U8Vec2, char>,
+    robot: U8Vec2,
+    cursor: usize,
+}
+
+#[derive(Debug)]
+struct Warehouse {
+    map: HashMap<U8Vec2, char>,
+    robot: U8Vec2,
+    directions: Vec<I16Vec2>,
+    cursor: usize,
+}
+
+impl Warehouse {
+    fn new(parsed: Parsed) -> Self {
+        Self {
+            map: parsed.map,
+            robot: parsed.start_pos,
+            directions: parsed.directions,
+            cursor: 0,
         }
-        let next_pos = next_pos.as_u8vec2();
+    }
 
-        let nc = parsed.map.get(&next_pos);
-        // println!("next_pos={:?}, c={:?}", next_pos, nc);
-        if nc.is_some_and(|c| c == &'#') {
-            // println!("[no move] current_pos={:?}", pos);
-            continue;
-        } else if nc.is_some_and(|c| c == &'O') {
-            if move_all_in_dir(&mut parsed.map, next_pos, dir) {
-                parsed.map.entry(pos).and_modify(|c| *c = '.');
-                // println!("[moved] current_pos={:?}, next_pos={:?}", pos, next_pos);
-                parsed.map.entry(next_pos).and_modify(|c| *c = '@');
-                pos = next_pos;
-            }
-        } else if nc.is_some_and(|c| c == &'.') {
-            parsed.map.entry(pos).and_modify(|c| *c = '.');
-            // println!("[advanced forward 1] next_pos={:?}", next_pos);
-            parsed.map.entry(next_pos).and_modify(|c| *c = '@');
-            pos = next_pos;
+    fn wide(parsed: Parsed) -> Self {
+        Self::new(widen(parsed))
+    }
+
+    fn robot(&self) -> U8Vec2 {
+        self.robot
+    }
+
+    fn moves_applied(&self) -> usize {
+        self.cursor
+    }
+
+    fn is_done(&self) -> bool {
+        self.cursor >= self.directions.len()
+    }
+
+    // returns `false` once every move has been applied
+    fn step(&mut self) -> bool {
+        let Some(&dir) = self.directions.get(self.cursor) else {
+            return false;
+        };
+        self.cursor += 1;
+
+        if push_boxes(&mut self.map, self.robot, dir) {
+            self.robot = (self.robot.as_i16vec2() + dir).as_u8vec2();
         }
+        true
+    }
 
-        // println!()
+    fn step_n(&mut self, n: usize) -> usize {
+        (0..n).take_while(|_| self.step()).count()
     }
 
-    // println!("{:?}", parsed.map);
+    fn run(&mut self) {
+        while self.step() {}
+    }
 
-    let result = parsed
-        .map
-        .into_iter()
-        .filter_map(|(k, v)| (v == 'O').then_some(k))
-        .map(|pos| 100 * pos.y as u64 + pos.x as u64)
-        .sum::<u64>();
+    // boxes are identified by their left edge, which is what the GPS sum uses
+    fn boxes(&self) -> HashSet<U8Vec2> {
+        self.map
+            .iter()
+            .filter_map(|(k, v)| matches!(v, 'O' | '[').then_some(*k))
+            .collect()
+    }
 
-    println!("part1: {}", result);
-}
+    fn gps_sum(&self) -> u64 {
+        self.boxes()
+            .into_iter()
+            .map(|pos| 100 * pos.y as u64 + pos.x as u64)
+            .sum()
+    }
 
-fn part2(mut parsed: Parsed) {
-    let mut pos = parsed.start_pos;
-    println!("start_pos={:?}", pos);
-    // println!("directions={:?}", parsed.directions);
-    // println!(
-    //     "blocks={:?}",
-    //     parsed
-    //         .map
-    //         .iter()
-    //         .filter(|(k, v)| v == &&b'#')
-    //         .collect::<Vec<_>>()
-    // );
-    //
-    for dir in parsed.directions.into_iter() {
-        // println!("dir={:?}", dir);
-        let next_pos = pos.as_i16vec2() + dir;
-        if next_pos.is_negative_bitmask() > 0 {
-            continue;
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            map: self.map.clone(),
+            robot: self.robot,
+            cursor: self.cursor,
         }
-        let next_pos = next_pos.as_u8vec2();
+    }
 
-        let nc = parsed.map.get(&next_pos);
-        // println!("next_pos={:?}, c={:?}", next_pos, nc);
-        if nc.is_some_and(|c| c == &'#') {
-            // println!("[no move] current_pos={:?}", pos);
-            continue;
-        } else if nc.is_some_and(|c| c == &'O') {
-            if move_all_in_dir(&mut parsed.map, next_pos, dir) {
-                parsed.map.entry(pos).and_modify(|c| *c = '.');
-                // println!("[moved] current_pos={:?}, next_pos={:?}", pos, next_pos);
-                parsed.map.entry(next_pos).and_modify(|c| *c = '@');
-                pos = next_pos;
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.map = snapshot.map;
+        self.robot = snapshot.robot;
+        self.cursor = snapshot.cursor;
+    }
+
+    fn render(&self) -> String {
+        let size = self.map.keys().fold(U8Vec2::ZERO, |acc, pos| acc.max(*pos));
+        let mut frame = String::new();
+        for y in 0..=size.y {
+            for x in 0..=size.x {
+                frame.push(*self.map.get(&U8Vec2::new(x, y)).unwrap_or(&' '));
             }
-        } else if nc.is_some_and(|c| c == &'.') {
-            parsed.map.entry(pos).and_modify(|c| *c = '.');
-            // println!("[advanced forward 1] next_pos={:?}", next_pos);
-            parsed.map.entry(next_pos).and_modify(|c| *c = '@');
-            pos = next_pos;
+            frame.push('\n');
         }
-
-        // println!()
+        frame
     }
+}
 
-    // println!("{:?}", parsed.map);
+fn part1(parsed: Parsed) {
+    let mut warehouse = Warehouse::new(parsed);
+    warehouse.run();
+    println!("part1: {}", warehouse.gps_sum());
+}
 
-    let result = parsed
-        .map
-        .into_iter()
-        .filter_map(|(k, v)| (v == 'O').then_some(k))
-        .map(|pos| 100 * pos.y as u64 + pos.x as u64)
-        .sum::<u64>();
+fn part2(parsed: Parsed) {
+    let mut warehouse = Warehouse::wide(parsed);
+    warehouse.run();
+    println!("part2: {}", warehouse.gps_sum());
+}
+
+fn replay(parsed: Parsed, moves: usize) {
+    let mut warehouse = Warehouse::wide(parsed);
+    let start = warehouse.snapshot();
 
-    println!("part2: {}", result);
+    let applied = warehouse.step_n(moves);
+    println!(
+        "after {} moves (done={}): robot={:?}, boxes={}",
+        applied,
+        warehouse.is_done(),
+        warehouse.robot(),
+        warehouse.boxes().len()
+    );
+    println!("{}", warehouse.render());
+
+    warehouse.restore(start);
+    println!("restored to move {}", warehouse.moves_applied());
 }
 
 fn main() -> anyhow::Result<()> {
+    let input = include_str!("../../inputs/day15.sample");
+    let (_, parsed) = parse(input)?;
+    replay(parsed, 10);
+
     let input = include_str!("../../inputs/day15.input");
     let (_, parsed) = parse(input)?;
     part1(parsed);
@@ -224,3 +289,65 @@ fn main() -> anyhow::Result<()> {
     part2(parsed);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Parsed {
+        parse(include_str!("../../inputs/day15.sample")).unwrap().1
+    }
+
+    #[test]
+    fn sample_gps_sums() {
+        let mut warehouse = Warehouse::new(sample());
+        warehouse.run();
+        assert_eq!(warehouse.gps_sum(), 10092);
+
+        let mut warehouse = Warehouse::wide(sample());
+        warehouse.run();
+        assert_eq!(warehouse.gps_sum(), 9021);
+    }
+
+    #[test]
+    fn step_n_stops_at_the_last_move() {
+        let mut warehouse = Warehouse::new(sample());
+        let total = warehouse.directions.len();
+        assert_eq!(warehouse.step_n(10), 10);
+        assert_eq!(warehouse.moves_applied(), 10);
+        assert_eq!(warehouse.step_n(total), total - 10);
+        assert!(warehouse.is_done());
+        assert_eq!(warehouse.step_n(1), 0);
+    }
+
+    #[test]
+    fn step_n_matches_single_steps() {
+        let mut stepped = Warehouse::wide(sample());
+        for _ in 0..100 {
+            stepped.step();
+        }
+        let mut warehouse = Warehouse::wide(sample());
+        warehouse.step_n(100);
+        assert_eq!(warehouse.robot(), stepped.robot());
+        assert_eq!(warehouse.render(), stepped.render());
+    }
+
+    #[test]
+    fn restore_round_trips() {
+        let mut warehouse = Warehouse::wide(sample());
+        warehouse.step_n(50);
+        let snapshot = warehouse.snapshot();
+        let (robot, boxes, frame) = (warehouse.robot(), warehouse.boxes(), warehouse.render());
+
+        warehouse.run();
+        let gps_sum = warehouse.gps_sum();
+        warehouse.restore(snapshot);
+        assert_eq!(warehouse.moves_applied(), 50);
+        assert_eq!(warehouse.robot(), robot);
+        assert_eq!(warehouse.boxes(), boxes);
+        assert_eq!(warehouse.render(), frame);
+
+        warehouse.run();
+        assert_eq!(warehouse.gps_sum(), gps_sum);
+    }
+}
@@ -1,6 +1,7 @@
-use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::collections::{BinaryHeap, HashMap, HashSet};
 
 use glam::{I8Vec2, Mat2, U8Vec2, Vec2};
+use itertools::Itertools;
 use nom::{
     bytes::complete::is_not, character::complete::line_ending, combinator::map, multi::fold_many1,
     sequence::tuple,
@@ -53,20 +54,18 @@ fn parse(input: &str) -> nom::IResult<&str, Parsed> {
 const ROT_90_CW: Mat2 = Mat2::from_cols(Vec2::new(0.0, 1.0), Vec2::new(-1.0, 0.0));
 const ROT_90_CCW: Mat2 = Mat2::from_cols(Vec2::new(0.0, -1.0), Vec2::new(1.0, 0.0));
 
+type State = (U8Vec2, I8Vec2);
+
 #[derive(Debug, PartialEq, Eq)]
 struct Node {
     pos: U8Vec2,
     dir: I8Vec2,
     path_cost: u32,
-    estimated_cost: u32,
 }
 
 impl Ord for Node {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match other.estimated_cost.cmp(&self.estimated_cost) {
-            std::cmp::Ordering::Equal => self.path_cost.cmp(&other.path_cost),
-            o => o,
-        }
+        other.path_cost.cmp(&self.path_cost)
     }
 }
 
@@ -76,121 +75,296 @@ impl PartialOrd for Node {
     }
 }
 
-fn part1(parsed: Parsed) {
-    let heu = |a: U8Vec2, b: U8Vec2| -> u32 {
-        let (ax, ay) = (a.x as i16, a.y as i16);
-        let (bx, by) = (b.x as i16, b.y as i16);
-
-        (ax.abs_diff(bx) + ay.abs_diff(by)) as u32
-    };
+#[derive(Debug)]
+struct Search {
+    start: State,
+    min_cost: u32,
+    goal_states: Vec<State>,
+    predecessors: HashMap<State, HashSet<State>>,
+}
 
+fn solve(parsed: &Parsed) -> Option<Search> {
     let mut costs = HashMap::new();
+    let mut predecessors: HashMap<State, HashSet<State>> = HashMap::new();
     let mut min_cost = None;
+    let mut goal_states = Vec::new();
 
-    let mut predecessors = HashMap::new();
-    // predecessors.insert(parsed.start_pos, HashSet::new());
+    let start = (parsed.start_pos, I8Vec2::X);
+    costs.insert(start, 0);
 
     let mut q = BinaryHeap::new();
     q.push(Node {
         pos: parsed.start_pos,
         dir: I8Vec2::X,
         path_cost: 0,
-        estimated_cost: heu(parsed.start_pos, parsed.goal_pos),
     });
 
     while let Some(Node {
         pos,
         dir,
         path_cost,
-        estimated_cost,
     }) = q.pop()
     {
-        if matches!(min_cost, Some(min_cost) if estimated_cost > min_cost) {
+        if costs.get(&(pos, dir)).is_some_and(|c| *c < path_cost) {
             continue;
         }
 
+        if matches!(min_cost, Some(min_cost) if path_cost > min_cost) {
+            break;
+        }
+
         if pos == parsed.goal_pos {
             min_cost = Some(path_cost);
+            goal_states.push((pos, dir));
+            continue;
         }
 
         // NOTE: could've also used `dir.perp()` and `-dir.perp()`
+        // turning around only ever pays off on the start tile, whose only exit
+        // may be behind the reindeer
         for (next_dir, next_path_cost) in [
             (dir.as_vec2(), path_cost + 1),
             (ROT_90_CCW.mul_vec2(dir.as_vec2()), (path_cost + 1) + 1000),
             (ROT_90_CW.mul_vec2(dir.as_vec2()), (path_cost + 1) + 1000),
+            (-dir.as_vec2(), (path_cost + 1) + 2000),
         ] {
             let next_pos = pos.as_i16vec2() + next_dir.as_i16vec2();
-            if next_pos.is_negative_bitmask() == 0
-                && parsed
+            if next_pos.is_negative_bitmask() > 0
+                || parsed
                     .pos_set
                     .get(&next_pos.as_u8vec2())
-                    .is_some_and(|c| *c != '#')
+                    .is_none_or(|c| *c == '#')
             {
-                let next_pos = next_pos.as_u8vec2();
-                let next_dir = next_dir.as_i8vec2();
-
-                predecessors
-                    .entry(next_pos)
-                    .and_modify(|e: &mut HashSet<U8Vec2>| {
-                        e.insert(pos);
-                    })
-                    .or_default();
-
-                let h = heu(next_pos, parsed.goal_pos);
-
-                if let Some(c) = costs.get_mut(&next_pos) {
-                    if *c > next_path_cost {
-                        *c = next_path_cost;
-                        q.push(Node {
-                            pos: next_pos,
-                            dir: next_dir,
-                            path_cost: next_path_cost,
-                            estimated_cost: next_path_cost + h,
-                        });
-                    }
-                } else {
-                    costs.insert(pos, next_path_cost);
-                    q.push(Node {
-                        pos: next_pos,
-                        dir: next_dir,
-                        path_cost: next_path_cost,
-                        estimated_cost: next_path_cost + h,
-                    });
+                continue;
+            }
+
+            let next = (next_pos.as_u8vec2(), next_dir.as_i8vec2());
+
+            match costs.get(&next) {
+                Some(c) if *c < next_path_cost => continue,
+                Some(c) if *c == next_path_cost => {
+                    predecessors.entry(next).or_default().insert((pos, dir));
+                    continue;
                 }
+                _ => {}
             }
+
+            costs.insert(next, next_path_cost);
+            predecessors.insert(next, HashSet::from([(pos, dir)]));
+            q.push(Node {
+                pos: next.0,
+                dir: next.1,
+                path_cost: next_path_cost,
+            });
         }
     }
 
-    // let mut ris = HashSet::new();
-    // count(&parsed.goal_pos, &predecessors, &mut ris);
-    //
-    // println!("{:?}", ris.len());
-    println!("part1: {:?}", min_cost);
+    Some(Search {
+        start,
+        min_cost: min_cost?,
+        goal_states,
+        predecessors,
+    })
 }
 
-fn count<'a>(
-    pos: &'a U8Vec2,
-    predec: &'a HashMap<U8Vec2, HashSet<U8Vec2>>,
-    ris: &mut HashSet<&'a U8Vec2>,
-) {
-    if ris.contains(pos) {
+fn count(state: State, predec: &HashMap<State, HashSet<State>>, ris: &mut HashSet<State>) {
+    if !ris.insert(state) {
         return;
     }
-    ris.insert(pos);
 
-    if let Some(v) = predec.get(pos) {
-        println!("{:?}", v);
+    if let Some(v) = predec.get(&state) {
         for p in v {
-            count(p, predec, ris);
+            count(*p, predec, ris);
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Move {
+    Forward,
+    TurnLeft,
+    TurnRight,
+}
+
+impl Move {
+    fn as_char(&self) -> char {
+        match self {
+            Move::Forward => 'F',
+            Move::TurnLeft => 'L',
+            Move::TurnRight => 'R',
+        }
+    }
+}
+
+fn moves_between(from: State, to: State) -> impl Iterator<Item = Move> {
+    // y grows downwards, so `perp()` is a clockwise (right) turn on screen
+    let turns: &[Move] = if to.1 == from.1 {
+        &[]
+    } else if to.1 == from.1.perp() {
+        &[Move::TurnRight]
+    } else if to.1 == -from.1 {
+        &[Move::TurnRight, Move::TurnRight]
+    } else {
+        &[Move::TurnLeft]
+    };
+    turns.iter().copied().chain([Move::Forward])
+}
+
+// walks the predecessor DAG backwards from each goal state, depth first, so
+// only the route currently being built is held in memory
+struct Routes<'a> {
+    predecessors: &'a HashMap<State, HashSet<State>>,
+    start: State,
+    goals: Vec<State>,
+    stack: Vec<(State, Vec<State>)>,
+}
+
+impl Routes<'_> {
+    fn frame(&self, state: State) -> (State, Vec<State>) {
+        let preds = self
+            .predecessors
+            .get(&state)
+            .map(|v| v.iter().copied().collect())
+            .unwrap_or_default();
+        (state, preds)
+    }
+}
+
+impl Iterator for Routes<'_> {
+    type Item = Vec<Move>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some(top) = self.stack.last_mut() else {
+                let goal = self.goals.pop()?;
+                let frame = self.frame(goal);
+                self.stack.push(frame);
+                continue;
+            };
+
+            if top.0 == self.start {
+                let route = self
+                    .stack
+                    .iter()
+                    .rev()
+                    .map(|(state, _)| *state)
+                    .tuple_windows()
+                    .flat_map(|(from, to)| moves_between(from, to))
+                    .collect();
+                self.stack.pop();
+                return Some(route);
+            }
+
+            match top.1.pop() {
+                Some(pred) => {
+                    let frame = self.frame(pred);
+                    self.stack.push(frame);
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+impl Search {
+    fn routes(&self) -> Routes<'_> {
+        Routes {
+            predecessors: &self.predecessors,
+            start: self.start,
+            goals: self.goal_states.clone(),
+            stack: Vec::new(),
+        }
+    }
+
+    fn optimal_routes(&self, limit: usize) -> Vec<Vec<Move>> {
+        self.routes().take(limit).collect()
+    }
+
+    fn route_count(&self) -> u128 {
+        fn count_from(
+            state: State,
+            start: State,
+            predec: &HashMap<State, HashSet<State>>,
+            memo: &mut HashMap<State, u128>,
+        ) -> u128 {
+            if state == start {
+                return 1;
+            }
+            if let Some(n) = memo.get(&state) {
+                return *n;
+            }
+
+            let n = predec.get(&state).map_or(0, |v| {
+                v.iter().map(|p| count_from(*p, start, predec, memo)).sum()
+            });
+            memo.insert(state, n);
+            n
+        }
+
+        let mut memo = HashMap::new();
+        self.goal_states
+            .iter()
+            .map(|state| count_from(*state, self.start, &self.predecessors, &mut memo))
+            .sum()
+    }
+}
+
+fn part1(search: &Search) {
+    println!("part1: {}", search.min_cost);
+}
+
+fn part2(search: &Search) {
+    let mut ris = HashSet::new();
+    for state in search.goal_states.iter() {
+        count(*state, &search.predecessors, &mut ris);
+    }
+
+    let tiles = ris.into_iter().map(|(pos, _)| pos).collect::<HashSet<_>>();
+    println!("part2: {}", tiles.len());
+}
+
 fn main() -> anyhow::Result<()> {
-    let input = include_str!("../../inputs/day16.sample");
+    let input = include_str!("../../inputs/day16.input");
     let (_, parsed) = parse(input)?;
-    // println!("{:?}", parsed);
-    part1(parsed);
+    let Some(search) = solve(&parsed) else {
+        anyhow::bail!("no path from start to goal");
+    };
+    part1(&search);
+    part2(&search);
+
+    println!("optimal routes: {}", search.route_count());
+    for route in search.optimal_routes(3) {
+        println!("{}", route.iter().map(Move::as_char).collect::<String>());
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route_strings(search: &Search) -> Vec<String> {
+        search
+            .optimal_routes(usize::MAX)
+            .iter()
+            .map(|route| route.iter().map(Move::as_char).collect())
+            .collect()
+    }
+
+    #[test]
+    fn solves_sample() {
+        let (_, parsed) = parse(include_str!("../../inputs/day16.sample")).unwrap();
+        assert_eq!(solve(&parsed).unwrap().min_cost, 7036);
+    }
+
+    #[test]
+    fn turns_around_when_the_only_exit_is_behind() {
+        let (_, parsed) = parse("#####\n#E.S#\n#####\n").unwrap();
+        let search = solve(&parsed).unwrap();
+        assert_eq!(search.min_cost, 2002);
+        assert_eq!(route_strings(&search), ["RRFF"]);
+    }
+}
@@ -2,12 +2,10 @@ use std::iter;
 
 use itertools::Itertools;
 use nom::{
-    Parser,
     bytes::complete::tag,
-    character::complete::{i32, line_ending, u8, u32, u64},
-    combinator::opt,
-    multi::{fold_many1, many1, separated_list1},
-    sequence::{separated_pair, terminated, tuple},
+    character::complete::{line_ending, u8, u64},
+    multi::separated_list1,
+    sequence::{terminated, tuple},
 };
 
 fn parse_registers(input: &str) -> nom::IResult<&str, [u64; 3]> {
@@ -40,12 +38,18 @@ fn parse_program(input: &str) -> nom::IResult<&str, (Vec<u8>, String)> {
 //     Ok((input, (program, program_str)))
 // }
 
-fn parse(input: &str) -> nom::IResult<&str, ([u64; 3], (Vec<u8>, String))> {
+#[derive(Debug)]
+struct Parsed {
+    registers: [u64; 3],
+    program: Vec<u8>,
+}
+
+fn parse(input: &str) -> nom::IResult<&str, Parsed> {
     let (input, registers) = parse_registers(input)?;
     let (input, _) = line_ending(input)?;
-    let (input, program) = parse_program(input)?;
+    let (input, (program, _)) = parse_program(input)?;
 
-    Ok((input, (registers, program)))
+    Ok((input, Parsed { registers, program }))
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -96,6 +100,25 @@ impl OpCode {
             OpCode::Cdv => OperandType::Combo,
         }
     }
+
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            OpCode::Adv => "adv",
+            OpCode::Bxl => "bxl",
+            OpCode::Bst => "bst",
+            OpCode::Jnz => "jnz",
+            OpCode::Bxc => "bxc",
+            OpCode::Out => "out",
+            OpCode::Bdv => "bdv",
+            OpCode::Cdv => "cdv",
+        }
+    }
+
+    fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+        (0..8)
+            .map(OpCode::from)
+            .find(|opcode| opcode.mnemonic().eq_ignore_ascii_case(mnemonic))
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -105,179 +128,466 @@ struct Instruction {
 }
 
 impl Instruction {
-    fn operand(&self, reg: &[u64; 3]) -> u64 {
+    fn operand(&self, reg: &[u64; 3]) -> Option<u64> {
+        match self.opcode.operand_type() {
+            OperandType::Literal => Some(self.arg as u64),
+            OperandType::Combo => match self.arg {
+                n @ 0..=3 => Some(n as u64),
+                4 => Some(reg[0]),
+                5 => Some(reg[1]),
+                6 => Some(reg[2]),
+                _ => None,
+            },
+            OperandType::Ignore => Some(u64::MIN),
+        }
+    }
+
+    // combo operands as they would appear in an expression: `3`, `a`, `b`, `c`
+    fn combo_expr(&self) -> String {
+        match self.arg {
+            4 => "a".to_string(),
+            5 => "b".to_string(),
+            6 => "c".to_string(),
+            n => n.to_string(),
+        }
+    }
+
+    fn pseudocode(&self) -> String {
+        let x = self.combo_expr();
+        match self.opcode {
+            OpCode::Adv => format!("a = a >> {}", x),
+            OpCode::Bxl => format!("b = b ^ {}", self.arg),
+            OpCode::Bst => format!("b = {} % 8", x),
+            OpCode::Jnz => format!("if (a > 0) goto {}", self.arg),
+            OpCode::Bxc => "b = b ^ c".to_string(),
+            OpCode::Out => format!("out({} % 8)", x),
+            OpCode::Bdv => format!("b = a >> {}", x),
+            OpCode::Cdv => format!("c = a >> {}", x),
+        }
+    }
+}
+
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mnemonic = self.opcode.mnemonic();
         match self.opcode.operand_type() {
-            OperandType::Literal => self.arg as u64,
+            OperandType::Literal | OperandType::Ignore => write!(f, "{} {}", mnemonic, self.arg),
             OperandType::Combo => match self.arg {
-                n @ 0..=3 => n as u64,
-                4 => reg[0],
-                5 => reg[1],
-                6 => reg[2],
-                _ => unreachable!(),
+                4 => write!(f, "{} combo(A)", mnemonic),
+                5 => write!(f, "{} combo(B)", mnemonic),
+                6 => write!(f, "{} combo(C)", mnemonic),
+                n => write!(f, "{} combo({})", mnemonic, n),
             },
-            OperandType::Ignore => u64::MIN,
         }
     }
 }
 
-fn part1(parsed: ([u64; 3], (Vec<u8>, String))) {
-    let (mut reg, (program, program_str)) = parsed;
-    reg[0] = 117440;
+// the same checks `Machine::new` makes, so no byte is dropped or misread
+fn validate(program: &[u8]) -> Result<(), VmError> {
+    if !program.len().is_multiple_of(2) {
+        return Err(VmError::OddLengthProgram(program.len()));
+    }
+    if let Some((ip, value)) = program.iter().enumerate().find(|(_, v)| **v > 7) {
+        return Err(VmError::InvalidValue { ip, value: *value });
+    }
+    Ok(())
+}
 
-    let instructions = program
-        .chunks_exact(2)
-        .map(|c| {
-            let opcode = c[0];
-            let arg = c[1];
+fn instructions(
+    program: &[u8],
+) -> Result<impl Iterator<Item = (usize, Instruction)> + '_, VmError> {
+    validate(program)?;
+    Ok(program.chunks_exact(2).enumerate().map(|(i, c)| {
+        (
+            i * 2,
             Instruction {
-                opcode: OpCode::from(opcode),
-                arg,
+                opcode: OpCode::from(c[0]),
+                arg: c[1],
+            },
+        )
+    }))
+}
+
+fn disassemble(program: &[u8]) -> Result<String, VmError> {
+    Ok(instructions(program)?
+        .map(|(ip, instruction)| format!("{:>3}: {}\n", ip, instruction))
+        .collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum AsmError {
+    UnknownMnemonic(String),
+    MissingOperand(String),
+    InvalidOperand { statement: String, reason: String },
+}
+
+impl std::fmt::Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AsmError::UnknownMnemonic(m) => write!(f, "unknown mnemonic `{}`", m),
+            AsmError::MissingOperand(statement) => {
+                write!(f, "`{}` is missing its operand", statement)
             }
-        })
+            AsmError::InvalidOperand { statement, reason } => {
+                write!(f, "invalid operand in `{}`: {}", statement, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+fn parse_operand(opcode: OpCode, operand: Option<&str>, statement: &str) -> Result<u8, AsmError> {
+    let invalid = |reason: &str| AsmError::InvalidOperand {
+        statement: statement.to_string(),
+        reason: reason.to_string(),
+    };
+    let literal = |operand: &str| {
+        operand
+            .parse::<u8>()
+            .ok()
+            .filter(|n| *n < 8)
+            .ok_or_else(|| invalid("literal operands must be in 0..=7"))
+    };
+
+    match (opcode.operand_type(), operand) {
+        (OperandType::Ignore, None) => Ok(0),
+        (_, None) => Err(AsmError::MissingOperand(statement.to_string())),
+        (OperandType::Literal | OperandType::Ignore, Some(operand)) => literal(operand),
+        (OperandType::Combo, Some(operand)) => {
+            // `combo(n)` is the raw encoding, so the reserved 7 the disassembler
+            // prints for unreachable code or data still round-trips
+            if let Some(raw) = operand
+                .strip_prefix("combo(")
+                .and_then(|o| o.strip_suffix(')'))
+            {
+                return match raw {
+                    "A" | "a" => Ok(4),
+                    "B" | "b" => Ok(5),
+                    "C" | "c" => Ok(6),
+                    _ => literal(raw),
+                };
+            }
+
+            match operand {
+                "A" | "a" => Ok(4),
+                "B" | "b" => Ok(5),
+                "C" | "c" => Ok(6),
+                _ => match literal(operand)? {
+                    n @ 0..=3 => Ok(n),
+                    _ => Err(invalid(
+                        "combo literals must be in 0..=3, use A, B or C for registers",
+                    )),
+                },
+            }
+        }
+    }
+}
+
+// accepts `;` or newline separated statements, optionally prefixed with the
+// `ip:` labels `disassemble` emits, so its output assembles back unchanged
+fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut program = Vec::new();
+
+    for statement in source.split([';', '\n']) {
+        let statement = statement
+            .split_once(':')
+            .map_or(statement, |(_, rest)| rest)
+            .trim();
+        if statement.is_empty() {
+            continue;
+        }
+
+        let mut parts = statement.split_whitespace();
+        let mnemonic = parts.next().unwrap();
+        let opcode = OpCode::from_mnemonic(mnemonic)
+            .ok_or_else(|| AsmError::UnknownMnemonic(mnemonic.to_string()))?;
+        let arg = parse_operand(opcode, parts.next(), statement)?;
+        if let Some(extra) = parts.next() {
+            return Err(AsmError::InvalidOperand {
+                statement: statement.to_string(),
+                reason: format!("unexpected `{}`", extra),
+            });
+        }
+
+        program.extend([opcode as u8, arg]);
+    }
+
+    Ok(program)
+}
+
+fn format_program(program: &[u8]) -> String {
+    format!("Program: {}", format_output(program))
+}
+
+// programs that end in the only `jnz 0` are a single `do {} while` loop;
+// anything else falls back to labelled statements with explicit gotos
+fn decompile(program: &[u8]) -> Result<String, VmError> {
+    let instructions = instructions(program)?.collect::<Vec<_>>();
+    let jumps = instructions
+        .iter()
+        .filter(|(_, i)| i.opcode == OpCode::Jnz)
         .collect::<Vec<_>>();
 
-    let mut ip = 0u32;
-    let mut out = String::new();
+    let is_loop = matches!(
+        (jumps.as_slice(), instructions.last()),
+        ([(jip, jump)], Some((lip, _))) if jip == lip && jump.arg == 0
+    );
 
-    while ip < instructions.len() as u32 * 2 {
-        let instruction = &instructions[(ip / 2) as usize];
-        let (opcode, operand) = (&instruction.opcode, instruction.operand(&reg));
+    if is_loop {
+        let mut out = String::from("do {\n");
+        for (_, instruction) in &instructions[..instructions.len() - 1] {
+            out.push_str(&format!("    {}\n", instruction.pseudocode()));
+        }
+        out.push_str("} while (a > 0)\n");
+        Ok(out)
+    } else {
+        Ok(instructions
+            .iter()
+            .map(|(ip, instruction)| format!("{:>3}: {}\n", ip, instruction.pseudocode()))
+            .collect())
+    }
+}
 
-        // println!("instruction={:?}", instruction);
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum VmError {
+    OddLengthProgram(usize),
+    InvalidValue { ip: usize, value: u8 },
+    InvalidComboOperand { ip: usize },
+    StepLimitExceeded(usize),
+}
 
-        // println!("reg={:?}", reg);
-        match opcode {
-            OpCode::Adv => {
-                let a = &mut reg[0];
-                // println!("[ADV] a_pre={:?}, operand={}", a, operand);
-                *a /= 2u64.pow(operand as u32);
-                // println!("[ADV] a_post={:?}, operand={}", a, operand);
+impl std::fmt::Display for VmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VmError::OddLengthProgram(len) => {
+                write!(f, "program has odd length {}", len)
+            }
+            VmError::InvalidValue { ip, value } => {
+                write!(f, "value {} at {} is not a 3-bit number", value, ip)
+            }
+            VmError::InvalidComboOperand { ip } => {
+                write!(f, "combo operand 7 at {} is reserved", ip)
+            }
+            VmError::StepLimitExceeded(steps) => {
+                write!(f, "program did not halt within {} steps", steps)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+// `a / 2^operand`; a combo operand can be a whole register, so shifts of 64 or
+// more must clear the value instead of being truncated to a `u32`
+fn div_pow2(a: u64, operand: u64) -> u64 {
+    u32::try_from(operand)
+        .ok()
+        .and_then(|s| a.checked_shr(s))
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Clone)]
+struct Machine {
+    reg: [u64; 3],
+    program: Vec<u8>,
+    ip: usize,
+    output: Vec<u8>,
+    steps: usize,
+    max_steps: Option<usize>,
+}
+
+impl Machine {
+    fn new(reg: [u64; 3], program: Vec<u8>) -> Result<Self, VmError> {
+        validate(&program)?;
+
+        Ok(Self {
+            reg,
+            program,
+            ip: 0,
+            output: Vec::new(),
+            steps: 0,
+            max_steps: None,
+        })
+    }
+
+    fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
 
-                ip += 2;
+    fn is_halted(&self) -> bool {
+        self.ip + 1 >= self.program.len()
+    }
+
+    // `jnz` may jump to an odd address, so instructions are decoded from the
+    // raw program at `ip` rather than from pre-split pairs
+    fn decode(&self) -> Option<Instruction> {
+        (!self.is_halted()).then(|| Instruction {
+            opcode: OpCode::from(self.program[self.ip]),
+            arg: self.program[self.ip + 1],
+        })
+    }
+
+    // returns `false` once the machine has halted
+    fn step(&mut self) -> Result<bool, VmError> {
+        Ok(self.step_traced()?.is_some())
+    }
+
+    fn step_traced(&mut self) -> Result<Option<TraceEntry>, VmError> {
+        let Some(instruction) = self.decode() else {
+            return Ok(None);
+        };
+        if self.max_steps.is_some_and(|max| self.steps >= max) {
+            return Err(VmError::StepLimitExceeded(self.steps));
+        }
+
+        let operand = instruction
+            .operand(&self.reg)
+            .ok_or(VmError::InvalidComboOperand { ip: self.ip })?;
+        let (ip, before, output_len) = (self.ip, self.reg, self.output.len());
+        let reg = &mut self.reg;
+
+        self.ip = match instruction.opcode {
+            OpCode::Adv => {
+                reg[0] = div_pow2(reg[0], operand);
+                self.ip + 2
             }
             OpCode::Bxl => {
-                let b = &mut reg[1];
-                *b ^= operand;
-
-                ip += 2;
+                reg[1] ^= operand;
+                self.ip + 2
             }
             OpCode::Bst => {
-                let b = &mut reg[1];
-                *b = operand % 8;
-
-                ip += 2;
+                reg[1] = operand % 8;
+                self.ip + 2
             }
             OpCode::Jnz => {
-                let a = &reg[0];
-                // println!("a={:?}", a);
-                if *a > 0 {
-                    ip = operand as u32;
+                if reg[0] != 0 {
+                    operand as usize
                 } else {
-                    ip += 2;
+                    self.ip + 2
                 }
             }
             OpCode::Bxc => {
-                let c = reg[2];
-                let b = &mut reg[1];
-                *b ^= c;
-
-                ip += 2;
+                reg[1] ^= reg[2];
+                self.ip + 2
             }
             OpCode::Out => {
-                let c = ((operand % 8) as u8 + 48) as char;
-                // println!("operand: {:?}, c: {:?}", operand, c);
-
-                if out.is_empty() {
-                    out.push(c);
-                } else {
-                    out.push(',');
-                    out.push(c);
-                }
-
-                println!("out={}", out);
-
-                ip += 2;
+                self.output.push((operand % 8) as u8);
+                self.ip + 2
             }
             OpCode::Bdv => {
-                let a = reg[0];
-                let b = &mut reg[1];
-                *b = a / 2u64.pow(operand as u32);
-
-                ip += 2;
+                reg[1] = div_pow2(reg[0], operand);
+                self.ip + 2
             }
             OpCode::Cdv => {
-                let a = reg[0];
-                let c = &mut reg[2];
-                *c = a / 2u64.pow(operand as u32);
+                reg[2] = div_pow2(reg[0], operand);
+                self.ip + 2
+            }
+        };
+        self.steps += 1;
+
+        Ok(Some(TraceEntry {
+            step: self.steps,
+            ip,
+            instruction,
+            operand,
+            before,
+            after: self.reg,
+            output: self.output.get(output_len).copied(),
+        }))
+    }
 
-                ip += 2;
+    fn trace(&mut self) -> Result<Vec<TraceEntry>, VmError> {
+        iter::from_fn(|| self.step_traced().transpose()).collect()
+    }
+
+    fn run(&mut self) -> Result<&[u8], VmError> {
+        while self.step()? {}
+        Ok(&self.output)
+    }
+
+    fn run_until_output(&mut self) -> Result<Option<u8>, VmError> {
+        let len = self.output.len();
+        while self.output.len() == len {
+            if !self.step()? {
+                return Ok(None);
             }
         }
-
-        println!("{:?}", reg);
+        Ok(self.output.last().copied())
     }
+}
 
-    println!("{:?}", out);
-    // println!("{:?}", instructions);
+#[derive(Debug, Clone)]
+struct TraceEntry {
+    step: usize,
+    ip: usize,
+    instruction: Instruction,
+    operand: u64,
+    before: [u64; 3],
+    after: [u64; 3],
+    output: Option<u8>,
+}
 
-    let mut reg = [0, reg[1], reg[2]];
+fn trace_to_csv(trace: &[TraceEntry]) -> String {
+    let mut csv = String::from(
+        "step,ip,instruction,operand,a_before,b_before,c_before,a_after,b_after,c_after,output\n",
+    );
+    for e in trace {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{},{}\n",
+            e.step,
+            e.ip,
+            e.instruction,
+            e.operand,
+            e.before[0],
+            e.before[1],
+            e.before[2],
+            e.after[0],
+            e.after[1],
+            e.after[2],
+            e.output.map(|o| o.to_string()).unwrap_or_default(),
+        ));
+    }
+    csv
+}
 
-    // aquine_p2_sample(&program, &mut reg);
-    aquine_p2_input(&program, &mut reg);
+fn trace_to_json(trace: &[TraceEntry]) -> String {
+    let entries = trace
+        .iter()
+        .map(|e| {
+            format!(
+                r#"{{"step":{},"ip":{},"instruction":"{}","operand":{},"before":[{}],"after":[{}],"output":{}}}"#,
+                e.step,
+                e.ip,
+                e.instruction,
+                e.operand,
+                e.before.iter().join(","),
+                e.after.iter().join(","),
+                e.output.map_or("null".to_string(), |o| o.to_string()),
+            )
+        })
+        .join(",\n  ");
+    format!("[\n  {}\n]\n", entries)
 }
 
-// do
-//      a = a >> 3
-//      out(a % 8)
-// while (a > 0)
+fn format_output(output: &[u8]) -> String {
+    output.iter().join(",")
+}
 
-// a = 117440;
-//   a := 117440 >> 3 -> 14680
-//   out(14680 % 8 -> 0)
-//
-// a = 14680;
-//   a := 14680 >> 3 -> 1835
-//   out(1835 % 8 -> 3)
-//
-// a = 1835;
-//   a := 1835 >> 3 -> 229
-//   out(229 % 8 -> 5)
-//
-// a = 229;
-//   a := 229 >> 3 -> 28
-//   out(28 % 8 -> 4)
-//
-// a = 28;
-//   a := 28 >> 3 -> 3
-//   out(3 % 8 -> 3)
-//
-// a = 3;
-//   a := 3 >> 3 -> 0
-//   out(0 % 8 -> 0)
-//
-// a = 0;
-fn aquine_p2_sample(target: &[u8], reg: &mut [u64]) {
-    // (a, b) in iter::zip(0..target.len() - 1, 1..target.len()).rev()
+// upper bound for running puzzle programs, which halt after a few hundred steps
+const MAX_STEPS: usize = 1_000_000;
 
-    for a in (0..target.len()).rev() {
-        reg[0] = (reg[0] << 3) + target[a] as u64;
-        println!("reg[0]={:?}", reg[0]);
-    }
-    reg[0] <<= 3;
+fn part1(parsed: &Parsed) -> anyhow::Result<()> {
+    let mut machine =
+        Machine::new(parsed.registers, parsed.program.clone())?.with_max_steps(MAX_STEPS);
+    println!("part1: {}", format_output(machine.run()?));
 
-    println!("{:?}", reg);
+    Ok(())
 }
 
-// do
-//   b = a % 8
-//   b = b ^ 7
-//   c = a >> b
-//   b = b ^ c
-//   b = b ^ 4
-//   out(b % 8)
-//   a = a >> 3
-// while (a > 0)
-
+// `decompile` shows the input program has this shape:
+//
 // do
 //   b = (a % 8) ^ 7
 //   c = a >> b
@@ -285,41 +595,204 @@ fn aquine_p2_sample(target: &[u8], reg: &mut [u64]) {
 //   out(b % 8)
 //   a = a >> 3
 // while (a > 0)
+//
+// each iteration consumes the low 3 bits of `a` and the last output only
+// depends on the top 3 bits, so `a` can be built from the last output digit
+// backwards, trying the 8 possible low bits at every level
+fn find_quine(reg: [u64; 3], program: &[u8]) -> Result<Option<u64>, VmError> {
+    fn search(
+        reg: [u64; 3],
+        program: &[u8],
+        i: usize,
+        prefix: u64,
+    ) -> Result<Option<u64>, VmError> {
+        for bits in 0..8 {
+            let a = (prefix << 3) | bits;
+            if a == 0 {
+                continue;
+            }
 
-// 31274997412295 < res < 119138258776848
+            let mut machine =
+                Machine::new([a, reg[1], reg[2]], program.to_vec())?.with_max_steps(MAX_STEPS);
+            let mut outputs_tail = || -> Result<bool, VmError> {
+                Ok(machine.run_until_output()? == Some(program[i])
+                    && machine.run()? == &program[i..])
+            };
+            // a candidate that never halts just isn't a quine
+            match outputs_tail() {
+                Ok(true) => {}
+                Ok(false) | Err(VmError::StepLimitExceeded(_)) => continue,
+                Err(e) => return Err(e),
+            }
 
-// a=117440; b=0; c=0;  out = "6,1,2,7,4,0"
-// b := (117440 % 8) ^ 7 -> 7
-// c := 117440 >> 7 -> 917
-// b := (7 >> 917) ^ 4 -> 4
-// out(4 % 8 -> 4)
-// a := 117440 >> 3 -> 14680
-//
-// a=14680; b=0; c=0;
-// b := (14680 % 8) ^ 7 -> 7
-// c := 0 >> 7 -> 0
-// b := (7 >> 0) ^ 4 -> 3
-// out(3 % 8 -> 0)
-// a := 117440 >> 3 -> 14680
-//
-fn aquine_p2_input(target: &[u8], reg: &mut [u64]) {
-    for a in (0..target.len()).rev() {
-        // reg[1] = (reg[0] % 8) ^ 7;
-        // reg[2] = reg[2] << reg[1];
-        // reg[1] = ((reg[1] ^ reg[2]) ^ 4 + target[a] as u64);
-        // reg[0] = (reg[0] << 3) + (reg[1] % 8);
+            if i == 0 {
+                return Ok(Some(a));
+            }
+            if let Some(a) = search(reg, program, i - 1, a)? {
+                return Ok(Some(a));
+            }
+        }
+
+        Ok(None)
+    }
+
+    if program.is_empty() {
+        return Ok(None);
+    }
+    search(reg, program, program.len() - 1, 0)
+}
+
+fn part2(parsed: &Parsed) -> anyhow::Result<()> {
+    match find_quine(parsed.registers, &parsed.program)? {
+        Some(a) => println!("part2: {}", a),
+        None => println!("part2: no quine found"),
     }
-    // reg[0] <<= 3;
 
-    println!("{:?}", reg);
+    Ok(())
 }
 
 fn main() -> anyhow::Result<()> {
     let input = include_str!("../../inputs/day17.input");
     let (_, parsed) = parse(input)?;
 
-    // println!("{:?}", parsed);
+    // `cargo run --bin day17 -- csv|json` dumps the part1 execution trace
+    if let Some(format) = std::env::args().nth(1) {
+        let mut machine =
+            Machine::new(parsed.registers, parsed.program.clone())?.with_max_steps(MAX_STEPS);
+        let trace = machine.trace()?;
+        match format.as_str() {
+            "csv" => print!("{}", trace_to_csv(&trace)),
+            "json" => print!("{}", trace_to_json(&trace)),
+            _ => anyhow::bail!("unknown trace format `{}`", format),
+        }
+        return Ok(());
+    }
+
+    print!("{}", disassemble(&parsed.program)?);
+    print!("{}", decompile(&parsed.program)?);
+
+    let program = assemble("bst A; bxl 7; cdv B; bxc; bxl 4; out B; adv 3; jnz 0")?;
+    println!("{}", format_program(&program));
 
-    part1(parsed);
+    part1(&parsed)?;
+    part2(&parsed)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_sample_program() {
+        let mut machine = Machine::new([729, 0, 0], vec![0, 1, 5, 4, 3, 0]).unwrap();
+        assert_eq!(machine.run(), Ok(&[4, 6, 3, 5, 6, 3, 5, 2, 1, 0][..]));
+    }
+
+    #[test]
+    fn shifts_by_whole_registers() {
+        // adv A with A = 2^32 + 1
+        let mut machine = Machine::new([(1 << 32) + 1, 0, 0], vec![0, 4]).unwrap();
+        machine.run().unwrap();
+        assert_eq!(machine.reg[0], 0);
+    }
+
+    #[test]
+    fn rejects_invalid_programs() {
+        assert_eq!(
+            Machine::new([0; 3], vec![0, 3, 5]).unwrap_err(),
+            VmError::OddLengthProgram(3)
+        );
+        assert_eq!(
+            Machine::new([0; 3], vec![0, 8]).unwrap_err(),
+            VmError::InvalidValue { ip: 1, value: 8 }
+        );
+        let mut machine = Machine::new([0; 3], vec![1, 0, 5, 7]).unwrap();
+        assert_eq!(
+            machine.run().unwrap_err(),
+            VmError::InvalidComboOperand { ip: 2 }
+        );
+    }
+
+    #[test]
+    fn stops_at_max_steps() {
+        // jnz 0 with A != 0 never halts
+        let mut machine = Machine::new([1, 0, 0], vec![3, 0])
+            .unwrap()
+            .with_max_steps(10);
+        assert_eq!(machine.run().unwrap_err(), VmError::StepLimitExceeded(10));
+
+        let mut machine = Machine::new([0, 0, 0], vec![3, 0])
+            .unwrap()
+            .with_max_steps(1);
+        assert_eq!(machine.run(), Ok(&[][..]));
+    }
+
+    #[test]
+    fn finds_sample_quine() {
+        assert_eq!(
+            find_quine([2024, 0, 0], &[0, 3, 5, 4, 3, 0]),
+            Ok(Some(117440))
+        );
+    }
+
+    #[test]
+    fn skips_candidates_that_never_halt() {
+        // jnz 0; out A: every non-zero `a` spins on the jump forever
+        assert_eq!(find_quine([0, 0, 0], &[3, 0, 5, 4]), Ok(None));
+    }
+
+    #[test]
+    fn assembler_round_trips_every_instruction() {
+        for opcode in 0..8 {
+            for arg in 0..8 {
+                let program = vec![opcode, arg];
+                let source = disassemble(&program).unwrap();
+                assert_eq!(assemble(&source), Ok(program), "{}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn assembler_round_trips_input() {
+        let (_, parsed) = parse(include_str!("../../inputs/day17.input")).unwrap();
+        assert_eq!(
+            assemble(&disassemble(&parsed.program).unwrap()),
+            Ok(parsed.program)
+        );
+    }
+
+    #[test]
+    fn disassembler_rejects_invalid_programs() {
+        assert_eq!(disassemble(&[0, 3, 5]), Err(VmError::OddLengthProgram(3)));
+        assert_eq!(
+            decompile(&[9, 0]),
+            Err(VmError::InvalidValue { ip: 0, value: 9 })
+        );
+    }
+
+    #[test]
+    fn assembles_mnemonics() {
+        assert_eq!(
+            assemble("bst A; bxl 7; cdv B; bxc; bxl 4; out B; adv 3; jnz 0"),
+            Ok(vec![2, 4, 1, 7, 7, 5, 4, 0, 1, 4, 5, 5, 0, 3, 3, 0])
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_operands() {
+        assert!(matches!(
+            assemble("adv 7"),
+            Err(AsmError::InvalidOperand { .. })
+        ));
+        assert!(matches!(
+            assemble("bxl 8"),
+            Err(AsmError::InvalidOperand { .. })
+        ));
+        assert!(matches!(assemble("out"), Err(AsmError::MissingOperand(_))));
+        assert!(matches!(
+            assemble("mul 3"),
+            Err(AsmError::UnknownMnemonic(_))
+        ));
+    }
+}
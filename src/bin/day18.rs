@@ -1,25 +1,25 @@
 use std::collections::{BinaryHeap, HashMap, HashSet};
 
-use glam::{I8Vec2, U8Vec2};
+use glam::IVec2;
 use nom::{
     Parser,
     bytes::complete::tag,
-    character::complete::{line_ending, u8},
-    multi::{fold_many1, many1},
+    character::complete::{i32, line_ending},
+    multi::many1,
     sequence::{separated_pair, terminated},
 };
 
-fn parse(input: &str) -> nom::IResult<&str, Vec<U8Vec2>> {
+fn parse(input: &str) -> nom::IResult<&str, Vec<IVec2>> {
     many1(terminated(
-        separated_pair(u8, tag(","), u8).map(|(x, y)| U8Vec2::new(x, y)),
+        separated_pair(i32, tag(","), i32).map(|(x, y)| IVec2::new(x, y)),
         line_ending,
     ))(input)
 }
 
 #[derive(Debug, PartialEq, Eq)]
 struct Node {
-    pos: U8Vec2,
-    dir: I8Vec2,
+    pos: IVec2,
+    dir: IVec2,
     path_cost: u32,
     estimated_cost: u32,
 }
@@ -39,125 +39,341 @@ impl PartialOrd for Node {
     }
 }
 
-fn path_cost_for_n_bytes(parsed: &[U8Vec2], n: usize) -> Option<u32> {
-    let bytes = parsed.iter().take(n).collect::<HashSet<_>>();
-    let heu = |a: U8Vec2, b: U8Vec2| -> u32 {
-        let (ax, ay) = (a.x as i16, a.y as i16);
-        let (bx, by) = (b.x as i16, b.y as i16);
+#[derive(Debug, Clone, Copy, Default)]
+struct Config {
+    width: Option<i32>,
+    height: Option<i32>,
+    byte_count: Option<usize>,
+}
 
-        (ax.abs_diff(bx) + ay.abs_diff(by)) as u32
-    };
+impl Config {
+    // `cargo run --bin day18 -- <width> <height> <byte count>`, any of which may be omitted
+    fn from_args() -> anyhow::Result<Self> {
+        let args = std::env::args().skip(1).collect::<Vec<_>>();
+        Ok(Self {
+            width: args.first().map(|a| a.parse()).transpose()?,
+            height: args.get(1).map(|a| a.parse()).transpose()?,
+            byte_count: args.get(2).map(|a| a.parse()).transpose()?,
+        })
+    }
+}
 
-    let mut costs = HashMap::new();
-    let mut min_cost = None;
-
-    let start_pos = U8Vec2::new(0, 0);
-    let goal_pos = U8Vec2::new(70, 70);
-
-    let mut queue = BinaryHeap::new();
-    queue.push(Node {
-        pos: start_pos,
-        dir: I8Vec2::Y,
-        path_cost: 0,
-        estimated_cost: heu(start_pos, goal_pos),
-    });
-
-    let mut predecessors = HashMap::new();
-
-    while let Some(Node {
-        pos,
-        dir,
-        path_cost,
-        estimated_cost,
-    }) = queue.pop()
-    {
-        if matches!(min_cost, Some(min_cost) if estimated_cost > min_cost) {
-            continue;
-        }
+#[derive(Debug)]
+struct MemorySpace {
+    size: IVec2,
+    byte_count: usize,
+    bytes: Vec<IVec2>,
+}
+
+impl MemorySpace {
+    // a missing size is the smallest grid holding every byte; a missing byte
+    // count is only known for the puzzle's own grids (12 for the 7x7 sample,
+    // 1024 for the 71x71 input)
+    fn new(bytes: Vec<IVec2>, config: Config) -> anyhow::Result<Self> {
+        let inferred = bytes.iter().fold(IVec2::ZERO, |acc, pos| acc.max(*pos)) + IVec2::ONE;
+        let size = IVec2::new(
+            config.width.unwrap_or(inferred.x),
+            config.height.unwrap_or(inferred.y),
+        );
+        anyhow::ensure!(
+            size.min_element() > 0,
+            "grid size must be positive, got {}x{}",
+            size.x,
+            size.y
+        );
+
+        let byte_count = match (config.byte_count, size.x, size.y) {
+            (Some(n), _, _) => n,
+            (None, 7, 7) => 12,
+            (None, 71, 71) => 1024,
+            (None, w, h) => anyhow::bail!("no default byte count for a {w}x{h} grid, pass one"),
+        };
+
+        Ok(Self {
+            size,
+            byte_count: byte_count.min(bytes.len()),
+            bytes,
+        })
+    }
 
-        if pos == goal_pos {
-            min_cost = Some(path_cost);
+    fn start_pos(&self) -> IVec2 {
+        IVec2::ZERO
+    }
+
+    fn goal_pos(&self) -> IVec2 {
+        self.size - IVec2::ONE
+    }
+
+    fn in_bounds(&self, pos: IVec2) -> bool {
+        pos.cmpge(IVec2::ZERO).all() && pos.cmplt(self.size).all()
+    }
+
+    fn shortest_path(&self, n: usize) -> Option<Vec<IVec2>> {
+        let bytes = self.bytes.iter().take(n).collect::<HashSet<_>>();
+        let heu = |a: IVec2, b: IVec2| -> u32 { (a - b).abs().element_sum() as u32 };
+
+        let start_pos = self.start_pos();
+        let goal_pos = self.goal_pos();
+        if bytes.contains(&start_pos) || bytes.contains(&goal_pos) {
+            return None;
         }
-        for next_dir in [dir, dir.perp(), -dir.perp()] {
-            // println!("{:?}, {:?}, {:?}, {:?}", dir, dir.perp(), -dir.perp(), pos);
-            let next_pos = pos.as_i16vec2() + next_dir.as_i16vec2();
-            if next_pos.is_negative_bitmask() > 0 {
-                continue;
-            }
 
-            let next_pos = next_pos.as_u8vec2();
+        let mut costs = HashMap::from([(start_pos, 0)]);
+        let mut came_from = HashMap::new();
 
-            if next_pos.x > goal_pos.x || next_pos.y > goal_pos.y {
-                continue;
+        let mut queue = BinaryHeap::new();
+        queue.push(Node {
+            pos: start_pos,
+            dir: IVec2::Y,
+            path_cost: 0,
+            estimated_cost: heu(start_pos, goal_pos),
+        });
+
+        while let Some(Node {
+            pos,
+            dir,
+            path_cost,
+            ..
+        }) = queue.pop()
+        {
+            if pos == goal_pos {
+                let mut path = vec![goal_pos];
+                while let Some(prev) = came_from.get(path.last().unwrap()) {
+                    path.push(*prev);
+                }
+                path.reverse();
+                return Some(path);
             }
 
-            if bytes.contains(&next_pos) {
+            if costs.get(&pos).is_some_and(|c| *c < path_cost) {
                 continue;
             }
 
-            predecessors
-                .entry(next_pos)
-                .and_modify(|e: &mut HashSet<U8Vec2>| {
-                    e.insert(pos);
-                })
-                .or_default()
-                .insert(pos);
-
-            let new_path_cost = path_cost + 1;
-            let h = heu(next_pos, goal_pos);
-
-            if let Some(c) = costs.get_mut(&next_pos) {
-                if *c > new_path_cost {
-                    *c = new_path_cost;
-                    queue.push(Node {
-                        pos: next_pos,
-                        dir: next_dir,
-                        path_cost: new_path_cost,
-                        estimated_cost: new_path_cost + h,
-                    });
+            for next_dir in [dir, dir.perp(), -dir.perp()] {
+                let next_pos = pos + next_dir;
+                if !self.in_bounds(next_pos) || bytes.contains(&next_pos) {
+                    continue;
+                }
+
+                let new_path_cost = path_cost + 1;
+                if costs.get(&next_pos).is_some_and(|c| *c <= new_path_cost) {
+                    continue;
                 }
-            } else {
+
                 costs.insert(next_pos, new_path_cost);
+                came_from.insert(next_pos, pos);
                 queue.push(Node {
                     pos: next_pos,
                     dir: next_dir,
                     path_cost: new_path_cost,
-                    estimated_cost: new_path_cost + h,
+                    estimated_cost: new_path_cost + heu(next_pos, goal_pos),
                 });
             }
         }
+
+        None
     }
 
-    min_cost
+    fn path_cost_for_n_bytes(&self, n: usize) -> Option<u32> {
+        self.shortest_path(n).map(|path| path.len() as u32 - 1)
+    }
 }
 
-fn part1(parsed: &[U8Vec2]) {
-    let min_cost = path_cost_for_n_bytes(parsed, 1024);
+fn part1(space: &MemorySpace) {
+    let min_cost = space.path_cost_for_n_bytes(space.byte_count);
 
     println!("part1: {:?}", min_cost);
 }
 
-fn part2(parsed: &[U8Vec2]) {
-    let mut l = 1025;
-    let mut h = parsed.len() - 1;
+fn first_blocking_byte_binary_search(space: &MemorySpace) -> Option<usize> {
+    // too many bytes may already have fallen for part 1, the empty grid always has a path
+    let mut l = if space.path_cost_for_n_bytes(space.byte_count).is_some() {
+        space.byte_count
+    } else {
+        0
+    };
+    let mut h = space.bytes.len();
 
+    // invariant: the path exists with `l` bytes fallen and not with `h + 1`
     while l < h {
         let m = (l + h) / 2;
-        if path_cost_for_n_bytes(parsed, m + 1).is_some() {
+        if space.path_cost_for_n_bytes(m + 1).is_some() {
             l = m + 1;
         } else {
             h = m;
         }
     }
 
-    println!("part2: {},{} (i={})", parsed[l].x, parsed[l].y, l);
+    (l < space.bytes.len()).then_some(l)
+}
+
+fn part2(space: &MemorySpace) {
+    match first_blocking_byte_binary_search(space) {
+        Some(i) => println!("part2: {},{} (i={})", space.bytes[i].x, space.bytes[i].y, i),
+        None => println!("part2: None"),
+    }
+}
+
+#[derive(Debug)]
+struct UnionFind {
+    parent: Vec<usize>,
+    rank: Vec<u8>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> Self {
+        Self {
+            parent: (0..n).collect(),
+            rank: vec![0; n],
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+
+        match self.rank[a].cmp(&self.rank[b]) {
+            std::cmp::Ordering::Less => self.parent[a] = b,
+            std::cmp::Ordering::Greater => self.parent[b] = a,
+            std::cmp::Ordering::Equal => {
+                self.parent[b] = a;
+                self.rank[a] += 1;
+            }
+        }
+    }
+
+    fn connected(&mut self, a: usize, b: usize) -> bool {
+        self.find(a) == self.find(b)
+    }
+}
+
+// starts from the fully corrupted grid and frees bytes in reverse; the byte
+// whose removal first joins start and goal is the first one that blocks the path
+fn first_blocking_byte(space: &MemorySpace) -> Option<usize> {
+    let (w, h) = (space.size.x as usize, space.size.y as usize);
+    let index = |pos: IVec2| pos.y as usize * w + pos.x as usize;
+
+    // a byte may fall on the same cell twice, only the first one matters
+    let mut corrupted_at = vec![None; w * h];
+    for (i, pos) in space.bytes.iter().enumerate() {
+        if space.in_bounds(*pos) {
+            corrupted_at[index(*pos)].get_or_insert(i);
+        }
+    }
+
+    let mut uf = UnionFind::new(w * h);
+    let mut free = corrupted_at.iter().map(Option::is_none).collect::<Vec<_>>();
+    let release = |uf: &mut UnionFind, free: &[bool], pos: IVec2| {
+        for dir in [IVec2::X, IVec2::Y, IVec2::NEG_X, IVec2::NEG_Y] {
+            let next_pos = pos + dir;
+            if space.in_bounds(next_pos) && free[index(next_pos)] {
+                uf.union(index(pos), index(next_pos));
+            }
+        }
+    };
+
+    for y in 0..space.size.y {
+        for x in 0..space.size.x {
+            let pos = IVec2::new(x, y);
+            if free[index(pos)] {
+                release(&mut uf, &free, pos);
+            }
+        }
+    }
+
+    let (start, goal) = (index(space.start_pos()), index(space.goal_pos()));
+    if uf.connected(start, goal) {
+        return None;
+    }
+
+    for (i, pos) in space.bytes.iter().enumerate().rev() {
+        if !space.in_bounds(*pos) || corrupted_at[index(*pos)] != Some(i) {
+            continue;
+        }
+
+        free[index(*pos)] = true;
+        release(&mut uf, &free, *pos);
+
+        if uf.connected(start, goal) {
+            return Some(i);
+        }
+    }
+
+    None
+}
+
+fn part2_union_find(space: &MemorySpace) {
+    match first_blocking_byte(space) {
+        Some(i) => println!(
+            "part2 (union-find): {},{} (i={})",
+            space.bytes[i].x, space.bytes[i].y, i
+        ),
+        None => println!("part2 (union-find): None"),
+    }
 }
 
 fn main() -> anyhow::Result<()> {
     let input = include_str!("../../inputs/day18.input");
     let (_, parsed) = parse(input)?;
-    // println!("{:?}", parsed);
-    part1(&parsed);
-    part2(&parsed);
+    let space = MemorySpace::new(parsed, Config::from_args()?)?;
+    part1(&space);
+    part2(&space);
+    part2_union_find(&space);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn space(input: &str, config: Config) -> MemorySpace {
+        MemorySpace::new(parse(input).unwrap().1, config).unwrap()
+    }
+
+    #[test]
+    fn union_find_matches_binary_search_on_sample() {
+        let space = space(include_str!("../../inputs/day18.sample"), Config::default());
+        assert_eq!(first_blocking_byte_binary_search(&space), Some(20));
+        assert_eq!(first_blocking_byte(&space), Some(20));
+        assert_eq!(space.bytes[20], IVec2::new(6, 1));
+    }
+
+    #[test]
+    fn union_find_matches_binary_search_on_input() {
+        let space = space(include_str!("../../inputs/day18.input"), Config::default());
+        let i = first_blocking_byte_binary_search(&space);
+        assert_eq!(i, Some(2849));
+        assert_eq!(first_blocking_byte(&space), i);
+    }
+
+    #[test]
+    fn binary_search_starts_below_byte_count() {
+        let config = Config {
+            byte_count: Some(999999),
+            ..Config::default()
+        };
+        let space = space(include_str!("../../inputs/day18.input"), config);
+        assert_eq!(first_blocking_byte_binary_search(&space), Some(2849));
+    }
+
+    #[test]
+    fn rejects_empty_grid() {
+        let config = Config {
+            width: Some(0),
+            height: Some(0),
+            byte_count: None,
+        };
+        let bytes = parse(include_str!("../../inputs/day18.sample")).unwrap().1;
+        assert!(MemorySpace::new(bytes, config).is_err());
+    }
+}
@@ -29,114 +29,149 @@ fn parse(input: &str) -> nom::IResult<&str, (HashSet<&str>, Vec<&str>)> {
     Ok((input, (patterns, towels)))
 }
 
-fn part1(parsed: &(HashSet<&str>, Vec<&str>)) {
-    let (patterns, towels) = parsed;
-
-    let mut cache = HashMap::new();
-
-    let result = towels
-        .iter()
-        .filter(|t| design_is_valid(t, patterns, &mut cache))
-        .count();
-
-    println!("part1: {}", result);
+#[derive(Debug, Default)]
+struct TrieNode {
+    children: HashMap<u8, usize>,
+    terminal: bool,
 }
 
-fn design_is_valid<'a>(
-    t: &'a str,
-    patterns: &HashSet<&'a str>,
-    cache: &mut HashMap<&'a str, bool>,
-) -> bool {
-    if patterns.contains(t) {
-        return true;
-    }
-
-    if let Some(b) = cache.get(t) {
-        return *b;
-    }
-
-    for i in 1..t.len() {
-        let (l, r) = t.split_at(i);
+#[derive(Debug)]
+struct Trie {
+    nodes: Vec<TrieNode>,
+}
 
-        let l_is_valid = design_is_valid(l, patterns, cache);
-        cache.insert(l, l_is_valid);
-        if !l_is_valid {
-            continue;
+impl Trie {
+    fn new<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut trie = Self {
+            nodes: vec![TrieNode::default()],
+        };
+        for pattern in patterns {
+            trie.insert(pattern);
         }
+        trie
+    }
 
-        let r_is_valid = design_is_valid(r, patterns, cache);
-        cache.insert(r, r_is_valid);
-        if !r_is_valid {
-            continue;
+    fn insert(&mut self, pattern: &str) {
+        let mut node = 0;
+        for b in pattern.bytes() {
+            node = match self.nodes[node].children.get(&b) {
+                Some(next) => *next,
+                None => {
+                    self.nodes.push(TrieNode::default());
+                    let next = self.nodes.len() - 1;
+                    self.nodes[node].children.insert(b, next);
+                    next
+                }
+            };
         }
-
-        cache.insert(t, true);
-        return true;
+        self.nodes[node].terminal = true;
     }
 
-    cache.insert(t, false);
-    false
-}
-
-fn count_valid_designs<'a>(
-    t: &'a str,
-    patterns: &HashSet<&'a str>,
-    cache: &mut HashMap<&'a str, u32>,
-) -> u32 {
-    if let Some(b) = cache.get(t) {
-        return *b;
+    // lengths of every pattern that is a prefix of `s`, shortest first
+    fn prefix_lengths<'a>(&'a self, s: &'a str) -> impl Iterator<Item = usize> + 'a {
+        s.bytes()
+            .scan(0, |node, b| {
+                *node = *self.nodes[*node].children.get(&b)?;
+                Some(*node)
+            })
+            .enumerate()
+            .filter_map(|(i, node)| self.nodes[node].terminal.then_some(i + 1))
     }
 
-    if patterns.contains(t) {
-        return 1;
+    // ways[i] is the number of arrangements of the suffix starting at `i`
+    fn arrangements(&self, design: &str) -> Vec<u128> {
+        let mut ways = vec![0; design.len() + 1];
+        ways[design.len()] = 1;
+        for i in (0..design.len()).rev() {
+            ways[i] = self.prefix_lengths(&design[i..]).map(|l| ways[i + l]).sum();
+        }
+        ways
     }
 
-    let mut c = HashMap::new();
-    for i in 1..t.len() {
-        let (l, r) = t.split_at(i);
-        println!("\nsplit at {}: l={}, r={}", i, l, r);
-
-        if design_is_valid(l, patterns, &mut c) || design_is_valid(r, patterns, &mut c) {
-            continue;
-        };
-
-        println!("valid");
+    fn count_arrangements(&self, design: &str) -> u128 {
+        self.arrangements(design)[0]
+    }
 
-        // println!(
-        //     "l_count({})={}, r_count({})={}",
-        //     l, l_is_valid, r, r_is_valid
-        // );
+    fn decompose<'a>(&self, design: &'a str) -> Option<Vec<&'a str>> {
+        let ways = self.arrangements(design);
+        if ways[0] == 0 {
+            return None;
+        }
 
-        cache.entry(t).and_modify(|e| *e += 1).or_insert(1);
+        let mut parts = Vec::new();
+        let mut i = 0;
+        while i < design.len() {
+            let l = self
+                .prefix_lengths(&design[i..])
+                .find(|l| ways[i + l] > 0)?;
+            parts.push(&design[i..i + l]);
+            i += l;
+        }
+        Some(parts)
     }
+}
 
-    *cache.get(&t).unwrap_or(&0)
+fn design_counts<'a>(trie: &Trie, towels: &[&'a str]) -> Vec<(&'a str, u128)> {
+    towels
+        .iter()
+        .map(|t| (*t, trie.count_arrangements(t)))
+        .collect()
 }
 
-fn part2(parsed: &(HashSet<&str>, Vec<&str>)) {
-    let (patterns, towels) = parsed;
+fn part1(counts: &[(&str, u128)]) {
+    let result = counts.iter().filter(|(_, n)| *n > 0).count();
 
-    let mut cache = HashMap::new();
+    println!("part1: {}", result);
+}
 
-    let result = towels
-        .iter()
-        .map(|t| count_valid_designs(t, patterns, &mut cache))
-        .sum::<u32>();
+fn part2(counts: &[(&str, u128)]) {
+    let result = counts.iter().map(|(_, n)| n).sum::<u128>();
 
     println!("part2: {}", result);
-    println!(
-        "part2: {:?}",
-        cache // .iter()
-              // .filter(|(k, _)| towels.contains(*k))
-              // .collect::<HashMap<_, _>>()
-    );
 }
 
 fn main() -> anyhow::Result<()> {
-    let input = include_str!("../../inputs/day19.sample");
+    let input = include_str!("../../inputs/day19.input");
     let (_, parsed) = parse(input)?;
-    println!("{:?}", parsed.0);
-    part1(&parsed);
-    part2(&parsed);
+    let trie = Trie::new(parsed.0.iter().copied());
+    let counts = design_counts(&trie, &parsed.1);
+    part1(&counts);
+    part2(&counts);
+
+    if let Some((design, parts)) = parsed
+        .1
+        .iter()
+        .find_map(|d| trie.decompose(d).map(|parts| (d, parts)))
+    {
+        println!("{} = {}", design, parts.join(" + "));
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_counts() {
+        let (_, (patterns, towels)) = parse(include_str!("../../inputs/day19.sample")).unwrap();
+        let counts = design_counts(&Trie::new(patterns), &towels);
+        assert_eq!(counts.iter().filter(|(_, n)| *n > 0).count(), 6);
+        assert_eq!(counts.iter().map(|(_, n)| n).sum::<u128>(), 16);
+    }
+
+    #[test]
+    fn decompositions_concatenate_to_the_design() {
+        let (_, (patterns, towels)) = parse(include_str!("../../inputs/day19.input")).unwrap();
+        let trie = Trie::new(patterns.iter().copied());
+        for design in towels {
+            match trie.decompose(design) {
+                Some(parts) => {
+                    assert_eq!(parts.concat(), design);
+                    assert!(parts.iter().all(|p| patterns.contains(p)));
+                }
+                None => assert_eq!(trie.count_arrangements(design), 0),
+            }
+        }
+    }
+}
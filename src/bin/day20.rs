@@ -1,6 +1,9 @@
 use core::hash;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::iter;
+use std::ops::Range;
+use std::thread;
+use std::time::Instant;
 
 use glam::{I8Vec2, I16Vec2, U8Vec2};
 use indexmap::IndexMap;
@@ -88,7 +91,7 @@ fn build_path(parents: &IndexMap<Node, usize>, mut i: usize) -> Vec<&Node> {
     v
 }
 
-fn solve(parsed: &Parsed) {
+fn track(parsed: &Parsed) -> Vec<Node> {
     let mut i = 0;
     let mut parents = IndexMap::new();
     parents.insert(
@@ -101,55 +104,181 @@ fn solve(parsed: &Parsed) {
 
     let mut seen = HashSet::new();
 
-    let path = loop {
-        if let Some((Node { pos, path_cost }, _)) = parents.get_index(i) {
-            if pos == &parsed.goal_pos {
-                break build_path(&parents, i);
+    loop {
+        let Some((Node { pos, path_cost }, _)) = parents.get_index(i) else {
+            return Vec::new();
+        };
+
+        if pos == &parsed.goal_pos {
+            return build_path(&parents, i).into_iter().copied().collect();
+        }
+
+        if seen.contains(pos) {
+            i += 1;
+            continue;
+        }
+
+        seen.insert(*pos);
+
+        let pos = *pos;
+        let path_cost = *path_cost;
+
+        let next_path_cost = path_cost + 1;
+        for dir in [I8Vec2::X, I8Vec2::NEG_Y, I8Vec2::NEG_X, I8Vec2::Y] {
+            let next_pos = pos.as_i16vec2() + dir.as_i16vec2();
+            if next_pos.is_negative_bitmask() > 0 {
+                continue;
+            }
+            let next_pos = next_pos.as_u8vec2();
+
+            if !parsed.walls.contains(&next_pos) {
+                parents
+                    .entry(Node {
+                        pos: next_pos,
+                        path_cost: next_path_cost,
+                    })
+                    .or_insert(i);
             }
+        }
+
+        i += 1;
+    }
+}
 
-            if seen.contains(pos) {
-                i += 1;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cheat {
+    start: U8Vec2,
+    end: U8Vec2,
+    saving: u32,
+}
+
+// a cheat may pass through walls for up to `duration` picoseconds, so it pairs
+// a track cell with any later one within that manhattan distance; the track is
+// ordered, so only ends at least `min_saving` steps ahead need to be visited
+fn cheats_from(path: &[Node], starts: Range<usize>, duration: u32, min_saving: u32) -> Vec<Cheat> {
+    let min_saving = min_saving.max(1);
+    let mut cheats = Vec::new();
+    for i in starts {
+        let node = &path[i];
+        let mut j = i + min_saving as usize;
+        while let Some(end) = path.get(j) {
+            let cheat_cost = (node.pos.as_i16vec2() - end.pos.as_i16vec2())
+                .abs()
+                .element_sum() as u32;
+            // each step along the track changes the distance by at most one
+            if cheat_cost > duration {
+                j += (cheat_cost - duration) as usize;
                 continue;
             }
 
-            seen.insert(*pos);
+            let saving = (end.path_cost - node.path_cost).saturating_sub(cheat_cost);
+            if saving >= min_saving {
+                cheats.push(Cheat {
+                    start: node.pos,
+                    end: end.pos,
+                    saving,
+                });
+            }
+            j += 1;
+        }
+    }
 
-            let pos = *pos;
-            let path_cost = *path_cost;
+    cheats
+}
+
+fn cheats(path: &[Node], duration: u32, min_saving: u32) -> Vec<Cheat> {
+    cheats_from(path, 0..path.len(), duration, min_saving)
+}
+
+fn cheats_parallel(path: &[Node], duration: u32, min_saving: u32) -> Vec<Cheat> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = path.len().div_ceil(threads).max(1);
+
+    thread::scope(|scope| {
+        (0..path.len())
+            .step_by(chunk_size)
+            .map(|start| {
+                let starts = start..(start + chunk_size).min(path.len());
+                scope.spawn(|| cheats_from(path, starts, duration, min_saving))
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+// track cost per cell in a dense row-major grid, `u32::MAX` off the track
+#[derive(Debug)]
+struct DistanceGrid {
+    size: I16Vec2,
+    costs: Vec<u32>,
+}
 
-            let next_path_cost = path_cost + 1;
-            for dir in [I8Vec2::X, I8Vec2::NEG_Y, I8Vec2::NEG_X, I8Vec2::Y] {
-                let next_pos = pos.as_i16vec2() + dir.as_i16vec2();
-                if next_pos.is_negative_bitmask() > 0 {
+impl DistanceGrid {
+    fn new(path: &[Node]) -> Self {
+        let size = path
+            .iter()
+            .fold(I16Vec2::ZERO, |acc, n| acc.max(n.pos.as_i16vec2()))
+            + I16Vec2::ONE;
+        let mut costs = vec![u32::MAX; size.x as usize * size.y as usize];
+        for n in path {
+            costs[n.pos.y as usize * size.x as usize + n.pos.x as usize] = n.path_cost;
+        }
+        Self { size, costs }
+    }
+
+    fn get(&self, pos: I16Vec2) -> Option<u32> {
+        if pos.cmplt(I16Vec2::ZERO).any() || pos.cmpge(self.size).any() {
+            return None;
+        }
+        let cost = self.costs[pos.y as usize * self.size.x as usize + pos.x as usize];
+        (cost != u32::MAX).then_some(cost)
+    }
+}
+
+// the full radius-`duration` diamond around every track cell, probing the
+// dense grid instead of a `HashMap`
+fn cheats_dense(path: &[Node], duration: u32, min_saving: u32) -> Vec<Cheat> {
+    let grid = DistanceGrid::new(path);
+    let radius = duration as i16;
+    let mut cheats = Vec::new();
+    for node in path {
+        for y in -radius..=radius {
+            let max_x = radius - y.abs();
+            for x in -max_x..=max_x {
+                let npos = node.pos.as_i16vec2() + I16Vec2::new(x, y);
+                let Some(cheat_pos_path_cost) = grid.get(npos) else {
                     continue;
-                }
-                let next_pos = next_pos.as_u8vec2();
-
-                if !parsed.walls.contains(&next_pos) {
-                    parents
-                        .entry(Node {
-                            pos: next_pos,
-                            path_cost: next_path_cost,
-                        })
-                        .or_insert(i);
+                };
+
+                let cheat_cost = (x.unsigned_abs() + y.unsigned_abs()) as u32;
+                let saving = cheat_pos_path_cost
+                    .saturating_sub(node.path_cost)
+                    .saturating_sub(cheat_cost);
+                if saving > 0 && saving >= min_saving {
+                    cheats.push(Cheat {
+                        start: node.pos,
+                        end: npos.as_u8vec2(),
+                        saving,
+                    });
                 }
             }
-
-            i += 1;
         }
-    };
+    }
+
+    cheats
+}
 
+fn cheats_hashmap(path: &[Node], duration: u32, min_saving: u32) -> Vec<Cheat> {
     let path_costs = path
         .iter()
         .map(|n| (n.pos, n.path_cost))
         .collect::<HashMap<_, _>>();
-    let total_cost = path_costs[&parsed.goal_pos];
-    println!("total_cost={}", total_cost);
 
-    let radius = 20i32;
-    let mut count = 0;
-    let saved = 100;
-    for node in &path {
+    let radius = duration as i32;
+    let mut cheats = Vec::new();
+    for node in path {
         for y in -radius..=radius {
             let max_x = radius - y.abs();
             for x in -max_x..=max_x {
@@ -160,36 +289,161 @@ fn solve(parsed: &Parsed) {
                 let npos = npos.as_u8vec2();
 
                 if let Some(&cheat_pos_path_cost) = path_costs.get(&npos) {
-                    let remaining_path_cost = total_cost - cheat_pos_path_cost;
-                    // let path_cost_from_start_pos = total_cost - node.path_cost;
-                    let new_path_cost =
-                        remaining_path_cost + node.path_cost + x.unsigned_abs() + y.unsigned_abs();
-
-                    // println!(
-                    //     "from={:?}, to={:?}, path_cost_from_cheat_pos={}, new_path_cost={}",
-                    //     node.pos,
-                    //     npos,
-                    //     path_cost_from_cheat_pos,
-                    //     node.path_cost + path_cost_from_cheat_pos + radius as u32,
-                    // );
-
-                    if new_path_cost <= total_cost - saved {
-                        // println!("from={:?}, to={:?}", node.pos, npos);
-                        count += 1;
+                    let cheat_cost = x.unsigned_abs() + y.unsigned_abs();
+                    let saving = cheat_pos_path_cost
+                        .saturating_sub(node.path_cost)
+                        .saturating_sub(cheat_cost);
+
+                    if saving > 0 && saving >= min_saving {
+                        cheats.push(Cheat {
+                            start: node.pos,
+                            end: npos,
+                            saving,
+                        });
                     }
                 }
             }
         }
     }
-    println!("{} cheats saved at least {} picoseconds", count, saved);
+
+    cheats
+}
+
+fn histogram(cheats: &[Cheat]) -> BTreeMap<u32, usize> {
+    cheats.iter().fold(BTreeMap::new(), |mut acc, cheat| {
+        *acc.entry(cheat.saving).or_default() += 1;
+        acc
+    })
+}
+
+fn print_histogram(cheats: &[Cheat]) {
+    for (saving, count) in histogram(cheats) {
+        if count == 1 {
+            println!("There is one cheat that saves {} picoseconds.", saving);
+        } else {
+            println!(
+                "There are {} cheats that save {} picoseconds.",
+                count, saving
+            );
+        }
+    }
+}
+
+fn part1(path: &[Node]) {
+    println!("part1: {}", cheats(path, 2, 100).len());
+}
+
+fn part2(path: &[Node]) {
+    println!("part2: {}", cheats(path, 20, 100).len());
+}
+
+fn bench(path: &[Node]) {
+    type CheatFn = fn(&[Node], u32, u32) -> Vec<Cheat>;
+    let variants: [(&str, CheatFn); 4] = [
+        ("hashmap", cheats_hashmap),
+        ("dense", cheats_dense),
+        ("track scan", cheats),
+        ("track parallel", cheats_parallel),
+    ];
+
+    for (name, f) in variants {
+        let start = Instant::now();
+        let count = f(path, 20, 100).len();
+        println!("{:>14}: {} cheats in {:?}", name, count, start.elapsed());
+    }
 }
 
 fn main() -> anyhow::Result<()> {
-    let input = include_str!("../../inputs/day20.input");
+    // `cargo run --release --bin day20 -- bench` compares the cheat scans
+    if std::env::args().nth(1).as_deref() == Some("bench") {
+        let input = include_str!("../../inputs/day20.input");
+        let (_, parsed) = parse(input)?;
+        bench(&track(&parsed));
+        return Ok(());
+    }
+
+    let input = include_str!("../../inputs/day20.sample");
     let (_, parsed) = parse(input)?;
-    // println!("{:?}", parsed);
+    let path = track(&parsed);
+    print_histogram(&cheats(&path, 2, 1));
+    print_histogram(&cheats(&path, 20, 50));
 
-    solve(&parsed);
+    let input = include_str!("../../inputs/day20.input");
+    let (_, parsed) = parse(input)?;
+    let path = track(&parsed);
+    part1(&path);
+    part2(&path);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut cheats: Vec<Cheat>) -> Vec<Cheat> {
+        cheats.sort_by_key(|c| (c.start.to_array(), c.end.to_array(), c.saving));
+        cheats
+    }
+
+    #[test]
+    fn sample_histograms() {
+        let (_, parsed) = parse(include_str!("../../inputs/day20.sample")).unwrap();
+        let path = track(&parsed);
+        assert_eq!(
+            histogram(&cheats(&path, 2, 1)),
+            BTreeMap::from([
+                (2, 14),
+                (4, 14),
+                (6, 2),
+                (8, 4),
+                (10, 2),
+                (12, 3),
+                (20, 1),
+                (36, 1),
+                (38, 1),
+                (40, 1),
+                (64, 1),
+            ])
+        );
+        assert_eq!(
+            histogram(&cheats(&path, 20, 50)),
+            BTreeMap::from([
+                (50, 32),
+                (52, 31),
+                (54, 29),
+                (56, 39),
+                (58, 25),
+                (60, 23),
+                (62, 20),
+                (64, 19),
+                (66, 12),
+                (68, 14),
+                (70, 12),
+                (72, 22),
+                (74, 4),
+                (76, 3),
+            ])
+        );
+    }
+
+    #[test]
+    fn scans_agree() {
+        let cases = [
+            (include_str!("../../inputs/day20.sample"), 2, 1),
+            (include_str!("../../inputs/day20.sample"), 20, 50),
+            (include_str!("../../inputs/day20.input"), 2, 100),
+        ];
+        for (input, duration, min_saving) in cases {
+            let (_, parsed) = parse(input).unwrap();
+            let path = track(&parsed);
+            let expected = sorted(cheats_hashmap(&path, duration, min_saving));
+            assert_eq!(sorted(cheats_dense(&path, duration, min_saving)), expected);
+            assert_eq!(sorted(cheats(&path, duration, min_saving)), expected);
+            assert_eq!(
+                sorted(cheats_parallel(&path, duration, min_saving)),
+                expected
+            );
+        }
+    }
+}
@@ -2,7 +2,7 @@ use std::collections::VecDeque;
 use std::iter;
 use std::{collections::HashMap, hash::Hash};
 
-use glam::{I8Vec2, Mat2, U8Vec2, Vec2};
+use glam::{I8Vec2, I16Vec2, U8Vec2};
 use indexmap::IndexMap;
 use itertools::Itertools;
 use nom::{
@@ -38,20 +38,60 @@ impl Hash for Node {
 #[derive(Debug)]
 struct CGrid {
     data: IndexMap<char, U8Vec2>,
-    width: u8,
+    cells: Vec<Option<char>>,
+    size: U8Vec2,
 }
 
 impl CGrid {
-    fn from_iter(iter: impl IntoIterator<Item = (char, U8Vec2)>, width: u8) -> Self {
-        Self {
-            data: IndexMap::from_iter(iter),
-            width,
+    // keys are laid out exactly as drawn, one row per line, with `gap` marking
+    // the holes no robot arm may point at
+    fn parse(art: &str, gap: char) -> anyhow::Result<Self> {
+        // only the blank lines around the art are dropped, rows are kept as is
+        // since a space gap may start or end one
+        let lines = art.lines().collect::<Vec<_>>();
+        let is_art = |l: &&str| !l.trim().is_empty();
+        let first = lines.iter().position(is_art).unwrap_or(lines.len());
+        let last = lines.iter().rposition(is_art).map_or(first, |i| i + 1);
+        let rows = &lines[first..last];
+        let width = rows.first().map_or(0, |r| r.chars().count());
+        anyhow::ensure!(width > 0, "keypad is empty");
+        anyhow::ensure!(
+            rows.iter().all(|r| r.chars().count() == width),
+            "keypad rows must all have the same width"
+        );
+
+        let mut data = IndexMap::new();
+        let mut cells = Vec::with_capacity(width * rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == gap {
+                    cells.push(None);
+                    continue;
+                }
+                let pos = U8Vec2::new(x as u8, y as u8);
+                anyhow::ensure!(data.insert(c, pos).is_none(), "duplicate key `{}`", c);
+                cells.push(Some(c));
+            }
         }
+
+        Ok(Self {
+            data,
+            cells,
+            size: U8Vec2::new(width as u8, rows.len() as u8),
+        })
     }
 
-    fn char(&self, pos: U8Vec2) -> Option<&char> {
-        let idx = pos.y * self.width + pos.x;
-        self.data.get_index(idx as usize).map(|(c, _)| c)
+    fn in_bounds(&self, pos: I16Vec2) -> bool {
+        pos.cmpge(I16Vec2::ZERO).all() && pos.cmplt(self.size.as_i16vec2()).all()
+    }
+
+    // `None` off the grid and on gaps
+    fn char(&self, pos: I16Vec2) -> Option<&char> {
+        if !self.in_bounds(pos) {
+            return None;
+        }
+        let idx = pos.y as usize * self.size.x as usize + pos.x as usize;
+        self.cells[idx].as_ref()
     }
 
     fn pos(&self, c: &char) -> Option<&U8Vec2> {
@@ -59,22 +99,6 @@ impl CGrid {
     }
 }
 
-fn build_path(q: &IndexMap<Node, usize>, pad: &CGrid, mut i: usize) -> Vec<char> {
-    let mut v = iter::from_fn(|| {
-        q.get_index(i).map(|(node, idx)| {
-            i = *idx;
-            *pad.char(node.pos).expect("Invalid key")
-        })
-    })
-    .collect::<Vec<_>>();
-
-    v.reverse();
-
-    v
-}
-
-const FLIP_Y: Mat2 = Mat2::from_cols(Vec2::new(1.0, 0.0), Vec2::new(0.0, -1.0));
-
 fn compute_map(pad: &CGrid) -> HashMap<(char, char), Vec<String>> {
     let mut r = HashMap::new();
 
@@ -124,15 +148,13 @@ fn compute_map(pad: &CGrid) -> HashMap<(char, char), Vec<String>> {
 
             for (dir, c) in dirs.into_iter().flatten() {
                 let next_pos = node.pos.as_i16vec2() + dir.as_i16vec2();
-                if next_pos.is_negative_bitmask() > 0 {
-                    continue;
-                }
-                let next_pos = next_pos.as_u8vec2();
-
-                if pad.char(next_pos).is_some_and(|nc| nc != &'-') {
+                if pad.char(next_pos).is_some() {
                     let mut seq = node.seq.clone();
                     seq.push(c);
-                    q.push_back(Node { pos: next_pos, seq });
+                    q.push_back(Node {
+                        pos: next_pos.as_u8vec2(),
+                        seq,
+                    });
                 }
             }
         }
@@ -141,126 +163,263 @@ fn compute_map(pad: &CGrid) -> HashMap<(char, char), Vec<String>> {
     r
 }
 
-fn minlengthd<'a>(
-    d: u32,
-    pseq: &'a String,
-    dmap: &'a HashMap<(char, char), Vec<String>>,
-    cache: &mut HashMap<(&'a String, u32), u64>,
-) -> u64 {
-    if let Some(l) = cache.get(&(pseq, d)) {
-        return *l;
+#[derive(Debug)]
+struct Keypad {
+    grid: CGrid,
+    moves: HashMap<(char, char), Vec<String>>,
+}
+
+impl Keypad {
+    fn parse(art: &str, gap: char) -> anyhow::Result<Self> {
+        let grid = CGrid::parse(art, gap)?;
+        let moves = compute_map(&grid);
+        Ok(Self { grid, moves })
     }
 
-    let inseq = format!("A{}", pseq);
-    if d == 1 {
-        inseq
-            .chars()
-            .tuple_windows()
-            .map(|(s, e)| {
-                // all seqs at depth 1 have the same length
-                dmap[&(s, e)][0].len() as u64
-            })
-            .sum()
-    } else {
-        let mut minlength = 0;
-
-        for (s, e) in inseq.chars().tuple_windows() {
-            minlength += dmap[&(s, e)]
-                .iter()
-                .map(|cseq| minlengthd(d - 1, cseq, dmap, cache))
-                .min()
-                .expect("Unable to calculate minimum");
+    // what this keypad types when its arm is driven by `inputs`, starting on `A`
+    fn press(&self, inputs: &str) -> anyhow::Result<String> {
+        let mut pos = *self
+            .grid
+            .pos(&'A')
+            .ok_or_else(|| anyhow::anyhow!("keypad has no `A` key"))?;
+        let mut out = String::new();
+
+        for (i, c) in inputs.chars().enumerate() {
+            let dir = match c {
+                'A' => {
+                    out.push(
+                        *self
+                            .grid
+                            .char(pos.as_i16vec2())
+                            .expect("arm is always on a key"),
+                    );
+                    continue;
+                }
+                '^' => I8Vec2::NEG_Y,
+                'v' => I8Vec2::Y,
+                '<' => I8Vec2::NEG_X,
+                '>' => I8Vec2::X,
+                _ => anyhow::bail!("`{}` is not a directional key", c),
+            };
+
+            let next_pos = pos.as_i16vec2() + dir.as_i16vec2();
+            anyhow::ensure!(
+                self.grid.in_bounds(next_pos),
+                "input {} (`{}`) moves the arm out of bounds",
+                i,
+                c
+            );
+            anyhow::ensure!(
+                self.grid.char(next_pos).is_some(),
+                "input {} (`{}`) aims the arm at the gap",
+                i,
+                c
+            );
+            pos = next_pos.as_u8vec2();
         }
 
-        cache.insert((pseq, d), minlength);
+        Ok(out)
+    }
+}
+
+const NUMERIC_KEYPAD: &str = "
+789
+456
+123
+-0A
+";
+
+const DIRECTIONAL_KEYPAD: &str = "
+-^A
+<v>
+";
+
+// chain[0] is the keypad the code is typed on, every following keypad is
+// operated by a robot aiming at the previous one, and a human types on the
+// robot holding the last one
+fn robot_chain<'a>(door: &'a Keypad, robot: &'a Keypad, depth: usize) -> Vec<&'a Keypad> {
+    iter::once(door)
+        .chain(iter::repeat_n(robot, depth))
+        .collect()
+}
 
-        minlength
+// human presses needed to make the robot at `level` move its arm from `s` to
+// `e` and press it
+fn press_cost(
+    level: usize,
+    s: char,
+    e: char,
+    chain: &[&Keypad],
+    cache: &mut HashMap<(usize, char, char), u64>,
+) -> anyhow::Result<u64> {
+    if let Some(l) = cache.get(&(level, s, e)) {
+        return Ok(*l);
     }
+
+    let seqs = chain[level]
+        .moves
+        .get(&(s, e))
+        .ok_or_else(|| anyhow::anyhow!("no key `{}` or `{}` on keypad {}", s, e, level))?;
+
+    let mut minlength = u64::MAX;
+    for seq in seqs {
+        minlength = minlength.min(seq_cost(level + 1, seq, chain, cache)?);
+    }
+
+    cache.insert((level, s, e), minlength);
+    Ok(minlength)
 }
 
-fn solve(
-    parsed: &[&str],
-    maps: &(
-        HashMap<(char, char), Vec<String>>,
-        HashMap<(char, char), Vec<String>>,
-    ),
-) -> anyhow::Result<()> {
-    let (nmap, dmap) = maps;
+fn seq_cost(
+    level: usize,
+    seq: &str,
+    chain: &[&Keypad],
+    cache: &mut HashMap<(usize, char, char), u64>,
+) -> anyhow::Result<u64> {
+    if level == chain.len() {
+        return Ok(seq.len() as u64);
+    }
 
-    let pseqs = parsed
-        .iter()
-        .map(|line| {
-            format!("A{}", line)
-                .chars()
-                .tuple_windows()
-                .map(|(s, e)| &nmap[&(s, e)])
-                .multi_cartesian_product()
-                .map(|pseqs| pseqs.into_iter().join(""))
-                .collect::<Vec<_>>()
-        })
-        .collect::<Vec<_>>();
-
-    let mut minlengths = vec![];
-    let mut cache = HashMap::new();
-    for pseq in &pseqs {
-        let mut minlength = u64::MAX;
-        for seq in pseq {
-            minlength = minlength.min(minlengthd(25, seq, dmap, &mut cache));
-        }
+    iter::once('A')
+        .chain(seq.chars())
+        .tuple_windows()
+        .map(|(s, e)| press_cost(level, s, e, chain, cache))
+        .sum()
+}
 
-        minlengths.push(minlength);
+// expands `seq` level by level, always picking a move with the minimal cost,
+// so the result is as long as `seq_cost` says; only viable for small chains
+fn keystrokes(
+    level: usize,
+    seq: &str,
+    chain: &[&Keypad],
+    cache: &mut HashMap<(usize, char, char), u64>,
+) -> anyhow::Result<String> {
+    if level == chain.len() {
+        return Ok(seq.to_string());
     }
 
+    let mut out = String::new();
+    for (s, e) in iter::once('A').chain(seq.chars()).tuple_windows() {
+        let best = press_cost(level, s, e, chain, cache)?;
+        let mut chosen = None;
+        for m in &chain[level].moves[&(s, e)] {
+            if seq_cost(level + 1, m, chain, cache)? == best {
+                chosen = Some(m);
+                break;
+            }
+        }
+        let m = chosen.expect("press_cost picked one of these moves");
+        out.push_str(&keystrokes(level + 1, m, chain, cache)?);
+    }
+
+    Ok(out)
+}
+
+fn optimal_keystrokes(code: &str, chain: &[&Keypad]) -> anyhow::Result<String> {
+    keystrokes(0, code, chain, &mut HashMap::new())
+}
+
+// feeds the human's keystrokes through every robot, from the one closest to
+// the human down to the door
+fn simulate(keys: &str, chain: &[&Keypad]) -> anyhow::Result<String> {
+    chain
+        .iter()
+        .rev()
+        .try_fold(keys.to_string(), |inputs, keypad| keypad.press(&inputs))
+}
+
+fn min_presses(code: &str, chain: &[&Keypad]) -> anyhow::Result<u64> {
+    seq_cost(0, code, chain, &mut HashMap::new())
+}
+
+fn solve(parsed: &[&str], chain: &[&Keypad]) -> anyhow::Result<u64> {
     let mut result = 0;
-    for (minlength, line) in iter::zip(minlengths, parsed) {
+    for line in parsed {
         let n = line[..line.len() - 1].parse::<u64>()?;
-        result += minlength * n;
+        result += min_presses(line, chain)? * n;
     }
 
-    println!("{}", result);
-
-    Ok(())
+    Ok(result)
 }
 
 fn main() -> anyhow::Result<()> {
     let input = include_str!("../../inputs/day21.input");
     let (_, parsed) = parse(input)?;
 
-    println!("{:?}", parsed);
-
-    let npad = CGrid::from_iter(
-        [
-            ('7', U8Vec2::new(0, 0)),
-            ('8', U8Vec2::new(1, 0)),
-            ('9', U8Vec2::new(2, 0)),
-            ('4', U8Vec2::new(0, 1)),
-            ('5', U8Vec2::new(1, 1)),
-            ('6', U8Vec2::new(2, 1)),
-            ('1', U8Vec2::new(0, 2)),
-            ('2', U8Vec2::new(1, 2)),
-            ('3', U8Vec2::new(2, 2)),
-            ('-', U8Vec2::new(0, 3)),
-            ('0', U8Vec2::new(1, 3)),
-            ('A', U8Vec2::new(2, 3)),
-        ],
-        3,
-    );
-    let npad_map = compute_map(&npad);
-
-    let dpad = CGrid::from_iter(
-        [
-            ('-', U8Vec2::new(0, 0)),
-            ('^', U8Vec2::new(1, 0)),
-            ('A', U8Vec2::new(2, 0)),
-            ('<', U8Vec2::new(0, 1)),
-            ('v', U8Vec2::new(1, 1)),
-            ('>', U8Vec2::new(2, 1)),
-        ],
-        3,
-    );
-    let dpad_map = compute_map(&dpad);
-
-    solve(&parsed, &(npad_map, dpad_map))?;
+    let npad = Keypad::parse(NUMERIC_KEYPAD, '-')?;
+    let dpad = Keypad::parse(DIRECTIONAL_KEYPAD, '-')?;
+
+    let chain = robot_chain(&npad, &dpad, 2);
+    for code in &parsed {
+        let keys = optimal_keystrokes(code, &chain)?;
+        println!("{}: {} (types {})", code, keys, simulate(&keys, &chain)?);
+    }
+
+    println!("part1: {}", solve(&parsed, &chain)?);
+    println!("part2: {}", solve(&parsed, &robot_chain(&npad, &dpad, 25))?);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keypads() -> (Keypad, Keypad) {
+        (
+            Keypad::parse(NUMERIC_KEYPAD, '-').unwrap(),
+            Keypad::parse(DIRECTIONAL_KEYPAD, '-').unwrap(),
+        )
+    }
+
+    #[test]
+    fn solves_sample() {
+        let (npad, dpad) = keypads();
+        let (_, parsed) = parse(include_str!("../../inputs/day21.sample")).unwrap();
+        assert_eq!(
+            solve(&parsed, &robot_chain(&npad, &dpad, 2)).unwrap(),
+            126384
+        );
+    }
+
+    #[test]
+    fn optimal_keystrokes_type_the_code() {
+        let (npad, dpad) = keypads();
+        let chain = robot_chain(&npad, &dpad, 2);
+        for input in [
+            include_str!("../../inputs/day21.sample"),
+            include_str!("../../inputs/day21.input"),
+        ] {
+            let (_, parsed) = parse(input).unwrap();
+            for code in parsed {
+                let keys = optimal_keystrokes(code, &chain).unwrap();
+                assert_eq!(keys.len() as u64, min_presses(code, &chain).unwrap());
+                assert_eq!(simulate(&keys, &chain).unwrap(), code);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_layouts_with_several_gaps() {
+        let pad = Keypad::parse("-^-\n<v>\n-A-", '-').unwrap();
+        assert_eq!(pad.press("^A<A").unwrap(), "v<");
+    }
+
+    #[test]
+    fn parses_space_gaps() {
+        let pad = Keypad::parse("789\n456\n123\n 0A", ' ').unwrap();
+        assert_eq!(pad.press("<A^A").unwrap(), "02");
+        let pad = Keypad::parse("\n ^A\n<v>\n", ' ').unwrap();
+        assert_eq!(pad.press("v<<A").unwrap(), "<");
+    }
+
+    #[test]
+    fn tells_gap_from_edge() {
+        let pad = Keypad::parse(DIRECTIONAL_KEYPAD, '-').unwrap();
+        let err = pad.press("<<").unwrap_err().to_string();
+        assert!(err.contains("gap"), "{err}");
+        let err = pad.press(">").unwrap_err().to_string();
+        assert!(err.contains("out of bounds"), "{err}");
+    }
+}
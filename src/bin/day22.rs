@@ -1,7 +1,5 @@
-use std::{
-    collections::{HashMap, HashSet},
-    iter,
-};
+use std::iter;
+use std::sync::OnceLock;
 
 use itertools::Itertools;
 use nom::{
@@ -40,53 +38,252 @@ fn next_secret(n: u32) -> u32 {
     n
 }
 
-fn part2(parsed: &[u32]) {
-    let mut prices = HashMap::<[i8; 4], (HashSet<usize>, u32)>::new();
-    let count = 2000;
-    for (i, succ) in parsed
+// `next_secret` only shifts, xors and masks, so it is linear over GF(2)^24 and
+// can be written as a 24x24 bit matrix; column `j` is the image of bit `j`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BitMatrix {
+    cols: [u32; 24],
+}
+
+impl BitMatrix {
+    fn identity() -> Self {
+        Self {
+            cols: std::array::from_fn(|j| 1 << j),
+        }
+    }
+
+    fn step() -> Self {
+        Self {
+            cols: std::array::from_fn(|j| next_secret(1 << j)),
+        }
+    }
+
+    fn apply(&self, v: u32) -> u32 {
+        (0..24)
+            .filter(|j| v >> j & 1 == 1)
+            .fold(0, |acc, j| acc ^ self.cols[j])
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        Self {
+            cols: other.cols.map(|c| self.apply(c)),
+        }
+    }
+
+    // Gauss-Jordan with column operations, i.e. right-multiplying by
+    // elementary matrices; mirroring them on the identity yields the inverse
+    // once `self` has been reduced to the identity
+    fn inverse(&self) -> Option<Self> {
+        let mut m = self.cols;
+        let mut inv = Self::identity().cols;
+
+        for bit in 0..24 {
+            let pivot = (bit..24).find(|&j| m[j] >> bit & 1 == 1)?;
+            m.swap(bit, pivot);
+            inv.swap(bit, pivot);
+
+            for j in 0..24 {
+                if j != bit && m[j] >> bit & 1 == 1 {
+                    m[j] ^= m[bit];
+                    inv[j] ^= inv[bit];
+                }
+            }
+        }
+
+        Some(Self { cols: inv })
+    }
+}
+
+// step^(2^i) for every bit of a u64 jump, built once; applying the ones set in
+// `k` jumps ahead without multiplying matrices per call
+fn step_powers() -> &'static [BitMatrix; 64] {
+    static POWERS: OnceLock<[BitMatrix; 64]> = OnceLock::new();
+    POWERS.get_or_init(|| {
+        let mut m = BitMatrix::step();
+        std::array::from_fn(|_| {
+            let p = m;
+            m = m.mul(&m);
+            p
+        })
+    })
+}
+
+fn step_inverse() -> &'static BitMatrix {
+    static INVERSE: OnceLock<BitMatrix> = OnceLock::new();
+    INVERSE.get_or_init(|| {
+        BitMatrix::step()
+            .inverse()
+            .expect("the secret step is a bijection")
+    })
+}
+
+fn nth_secret(n: u32, k: u64) -> u32 {
+    step_powers()
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| k >> i & 1 == 1)
+        .fold(n, |n, (_, m)| m.apply(n))
+}
+
+fn prev_secret(n: u32) -> u32 {
+    step_inverse().apply(n)
+}
+
+fn part1(parsed: &[u32]) {
+    let result = parsed
         .iter()
         .map(|&n| {
             iter::successors(Some(n), |&n| Some(next_secret(n)))
-                .tuple_windows::<(_, _, _, _, _)>()
-                .take((count + 1) - 4)
+                .nth(2000)
+                .unwrap() as u64
         })
+        .sum::<u64>();
+
+    println!("part1: {}", result);
+}
+
+// each change is in -9..=9, so a window of four fits in 19^4 slots
+const SEQUENCES: usize = 19 * 19 * 19 * 19;
+
+fn encode(k: [i8; 4]) -> usize {
+    k.iter().fold(0, |acc, d| acc * 19 + (d + 9) as usize)
+}
+
+fn decode(mut idx: usize) -> [i8; 4] {
+    let mut k = [0; 4];
+    for d in k.iter_mut().rev() {
+        *d = (idx % 19) as i8 - 9;
+        idx /= 19;
+    }
+    k
+}
+
+// every (secret index, four price changes, price) a buyer goes through
+fn price_windows(n: u32, count: usize) -> impl Iterator<Item = (usize, [i8; 4], u32)> {
+    iter::successors(Some(n), |&n| Some(next_secret(n)))
+        .tuple_windows::<(_, _, _, _, _)>()
+        .take((count + 1) - 4)
         .enumerate()
-    {
-        for (a, b, c, d, e) in succ {
+        .map(|(i, (a, b, c, d, e))| {
             let k = [
                 (b % 10) as i8 - (a % 10) as i8,
                 (c % 10) as i8 - (b % 10) as i8,
                 (d % 10) as i8 - (c % 10) as i8,
                 (e % 10) as i8 - (d % 10) as i8,
             ];
-            let v = e % 10;
-
-            prices
-                .entry(k)
-                .and_modify(|e| {
-                    if !e.0.contains(&i) {
-                        e.0.insert(i);
-                        e.1 += v;
-                    }
-                })
-                .or_insert((HashSet::from_iter([i]), v));
+            (i + 4, k, e % 10)
+        })
+}
+
+const COUNT: usize = 2000;
+
+fn part2(parsed: &[u32]) -> Option<([i8; 4], u32)> {
+    let mut totals = vec![0u32; SEQUENCES];
+    // buyer index + 1 that last sold on each sequence; a buyer only ever
+    // sells on the first occurrence
+    let mut last_seen = vec![0usize; SEQUENCES];
+    for (i, &n) in parsed.iter().enumerate() {
+        for (_, k, price) in price_windows(n, COUNT) {
+            let k = encode(k);
+            if last_seen[k] != i + 1 {
+                last_seen[k] = i + 1;
+                totals[k] += price;
+            }
         }
     }
 
-    if let Some((key, value)) = prices.iter().max_by(|a, b| a.1.1.cmp(&b.1.1)) {
-        println!(
-            "The key with the maximum value is: {:?} with value {:?}",
-            key, value.1
-        );
-    } else {
-        println!("The map is empty.");
+    totals
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, v)| **v)
+        .filter(|(_, v)| **v > 0)
+        .map(|(k, v)| (decode(k), *v))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sale {
+    step: usize,
+    price: u32,
+}
+
+// for each buyer, the first secret at which `key` shows up and what they sell for
+fn breakdown(parsed: &[u32], key: [i8; 4]) -> Vec<(u32, Option<Sale>)> {
+    parsed
+        .iter()
+        .map(|&n| {
+            let sale = price_windows(n, COUNT)
+                .find(|(_, k, _)| *k == key)
+                .map(|(step, _, price)| Sale { step, price });
+            (n, sale)
+        })
+        .collect()
+}
+
+fn print_breakdown(parsed: &[u32], key: [i8; 4]) {
+    let sales = breakdown(parsed, key);
+    for (n, sale) in &sales {
+        match sale {
+            Some(Sale { step, price }) => {
+                println!("buyer {}: sells {} at secret #{}", n, price, step)
+            }
+            None => println!("buyer {}: never sells", n),
+        }
     }
+
+    let total = sales
+        .iter()
+        .filter_map(|(_, s)| s.map(|s| s.price))
+        .sum::<u32>();
+    println!("total: {}", total);
 }
 
 fn main() -> anyhow::Result<()> {
     let input = include_str!("../../inputs/day22.input");
     let (_, parsed) = parse(input)?;
-    // println!("{:?}", parsed);
-    part2(&parsed);
+    part1(&parsed);
+    println!(
+        "10^18th secret of the first buyer: {:?}",
+        parsed.first().map(|&n| nth_secret(n, 10u64.pow(18)))
+    );
+    println!(
+        "secret preceding the first buyer's: {:?}",
+        parsed.first().map(|&n| prev_secret(n))
+    );
+    match part2(&parsed) {
+        Some((key, bananas)) => println!("part2: {} (sequence {:?})", bananas, key),
+        None => println!("part2: no buyer sells"),
+    }
+
+    let input = include_str!("../../inputs/day22.sample");
+    let (_, parsed) = parse(input)?;
+    if let Some((key, _)) = part2(&parsed) {
+        print_breakdown(&parsed, key);
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inverse_undoes_step() {
+        assert_eq!(BitMatrix::step().mul(step_inverse()), BitMatrix::identity());
+    }
+
+    #[test]
+    fn jump_ahead_matches_iteration() {
+        let (_, parsed) = parse(include_str!("../../inputs/day22.input")).unwrap();
+        for &n in parsed.iter().take(16) {
+            let iterative = iter::successors(Some(n), |&n| Some(next_secret(n)))
+                .take(2001)
+                .collect::<Vec<_>>();
+            for k in [0, 1, 2, 10, 1999, 2000] {
+                assert_eq!(nth_secret(n, k as u64), iterative[k]);
+            }
+            for w in iterative.windows(2).take(100) {
+                assert_eq!(prev_secret(w[1]), w[0]);
+            }
+        }
+    }
+}
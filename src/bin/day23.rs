@@ -1,6 +1,8 @@
 use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::iter;
+use std::path::Path;
 
-use indexmap::IndexSet;
 use itertools::Itertools;
 use nom::{
     Parser,
@@ -9,7 +11,12 @@ use nom::{
     multi::fold_many1,
     sequence::{separated_pair, terminated},
 };
-use petgraph::{Graph, Undirected, graph::NodeIndex};
+use petgraph::{
+    Graph, Undirected,
+    dot::{Config as DotConfig, Dot},
+    graph::NodeIndex,
+    visit::EdgeRef,
+};
 
 fn parse(input: &str) -> nom::IResult<&str, Graph<&str, (), petgraph::Undirected>> {
     fold_many1(
@@ -28,109 +35,552 @@ fn parse(input: &str) -> nom::IResult<&str, Graph<&str, (), petgraph::Undirected
     .parse(input)
 }
 
-fn part1(parsed: &Graph<&str, (), Undirected>) {
-    let mut triangles = HashSet::new();
-    let mut count = 0;
+// repeatedly removes a vertex of minimum remaining degree; orienting every
+// edge towards the vertex removed later leaves each vertex with at most
+// `degeneracy` out-neighbours
+fn degeneracy_order(graph: &Graph<&str, (), Undirected>) -> Vec<NodeIndex> {
+    let mut degree = graph
+        .node_indices()
+        .map(|n| graph.neighbors(n).count())
+        .collect::<Vec<_>>();
+    let mut removed = vec![false; graph.node_count()];
+    let mut order = Vec::with_capacity(graph.node_count());
 
-    for e in parsed.edge_indices() {
-        let (a, b) = parsed.edge_endpoints(e).unwrap();
-        let na = parsed.neighbors(a).collect::<HashSet<_>>();
-        let nb = parsed.neighbors(b).collect::<HashSet<_>>();
+    for _ in 0..graph.node_count() {
+        let v = graph
+            .node_indices()
+            .filter(|n| !removed[n.index()])
+            .min_by_key(|n| degree[n.index()])
+            .unwrap();
 
-        for &c in na.intersection(&nb) {
-            let mut nodes = [a, b, c];
-            nodes.sort();
-            triangles.insert((nodes[0], nodes[1], nodes[2]));
+        removed[v.index()] = true;
+        for n in graph.neighbors(v) {
+            if !removed[n.index()] {
+                degree[n.index()] -= 1;
+            }
         }
+        order.push(v);
     }
 
-    for &(a, b, c) in &triangles {
-        if parsed[a].starts_with('t') || parsed[b].starts_with('t') || parsed[c].starts_with('t') {
-            count += 1;
+    order
+}
+
+fn for_each_k_clique(
+    graph: &Graph<&str, (), Undirected>,
+    k: usize,
+    mut f: impl FnMut(&[NodeIndex]),
+) {
+    fn extend(
+        later: &[HashSet<NodeIndex>],
+        k: usize,
+        clique: &mut Vec<NodeIndex>,
+        candidates: &HashSet<NodeIndex>,
+        f: &mut impl FnMut(&[NodeIndex]),
+    ) {
+        if clique.len() == k {
+            f(clique);
+            return;
+        }
+
+        for &v in candidates {
+            clique.push(v);
+            let next = candidates
+                .intersection(&later[v.index()])
+                .copied()
+                .collect::<HashSet<_>>();
+            extend(later, k, clique, &next, f);
+            clique.pop();
         }
     }
 
-    println!("part1: {:?}", count);
-}
+    if k == 0 {
+        return;
+    }
 
-fn pivot(
-    p: &IndexSet<NodeIndex>,
-    x: &IndexSet<NodeIndex>,
-    graph: &Graph<&str, (), Undirected>,
-) -> NodeIndex {
-    *p.union(x)
-        .max_by_key(|&&node| {
+    let order = degeneracy_order(graph);
+    let mut rank = vec![0; graph.node_count()];
+    for (i, n) in order.iter().enumerate() {
+        rank[n.index()] = i;
+    }
+
+    // only following edges towards later vertices visits each clique once
+    let later = graph
+        .node_indices()
+        .map(|v| {
             graph
-                .neighbors(node)
-                .filter(|n| p.contains(n) || x.contains(n))
-                .count()
+                .neighbors(v)
+                .filter(|n| rank[n.index()] > rank[v.index()])
+                .collect::<HashSet<_>>()
         })
-        .unwrap()
+        .collect::<Vec<_>>();
+
+    let mut clique = Vec::with_capacity(k);
+    for v in order {
+        clique.push(v);
+        extend(&later, k, &mut clique, &later[v.index()], &mut f);
+        clique.pop();
+    }
+}
+
+// every k-clique whose node names satisfy `filter`, each sorted by name;
+// `filter` sees the names in that same sorted order
+fn k_cliques(
+    graph: &Graph<&str, (), Undirected>,
+    k: usize,
+    filter: impl Fn(&[&str]) -> bool,
+) -> Vec<Vec<NodeIndex>> {
+    let mut cliques = Vec::new();
+    for_each_k_clique(graph, k, |clique| {
+        let clique = clique
+            .iter()
+            .copied()
+            .sorted_by_key(|&n| graph[n])
+            .collect_vec();
+        let names = clique.iter().map(|&n| graph[n]).collect::<Vec<_>>();
+        if filter(&names) {
+            cliques.push(clique);
+        }
+    });
+    cliques.sort_by_cached_key(|clique| clique.iter().map(|&n| graph[n]).collect::<Vec<_>>());
+    cliques
+}
+
+// same as `k_cliques(..).len()` without collecting the cliques
+fn count_k_cliques(
+    graph: &Graph<&str, (), Undirected>,
+    k: usize,
+    filter: impl Fn(&[&str]) -> bool,
+) -> usize {
+    let mut count = 0;
+    let mut names = Vec::with_capacity(k);
+    for_each_k_clique(graph, k, |clique| {
+        names.clear();
+        names.extend(clique.iter().map(|&n| graph[n]));
+        names.sort_unstable();
+        if filter(&names) {
+            count += 1;
+        }
+    });
+    count
+}
+
+fn part1(parsed: &Graph<&str, (), Undirected>) {
+    let count = count_k_cliques(parsed, 3, |nodes| nodes.iter().any(|n| n.starts_with('t')));
+
+    println!("part1: {:?}", count);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BitSet {
+    words: Vec<u64>,
 }
 
-fn get_maximal_cliques(
-    parsed: &Graph<&str, (), Undirected>,
-    cr: IndexSet<NodeIndex>,
-    p: IndexSet<NodeIndex>,
-    mut x: IndexSet<NodeIndex>,
-) -> Vec<IndexSet<NodeIndex>> {
-    if p.is_empty() && x.is_empty() {
-        return Vec::from_iter([cr]);
+impl BitSet {
+    fn new(len: usize) -> Self {
+        Self {
+            words: vec![0; len.div_ceil(64)],
+        }
     }
 
-    let mut r = Vec::new();
+    fn full(len: usize) -> Self {
+        let mut set = Self::new(len);
+        for i in 0..len {
+            set.insert(i);
+        }
+        set
+    }
 
-    let pvt = pivot(&p, &x, parsed);
+    fn insert(&mut self, i: usize) {
+        self.words[i / 64] |= 1 << (i % 64);
+    }
 
-    for v in p
-        .difference(&parsed.neighbors(pvt).collect::<IndexSet<_>>())
-        .copied()
-    {
-        let nv = parsed.neighbors(v).collect::<IndexSet<_>>();
-        let mut cr = cr.iter().copied().collect::<IndexSet<_>>();
-        cr.insert(v);
+    fn remove(&mut self, i: usize) {
+        self.words[i / 64] &= !(1 << (i % 64));
+    }
 
-        r.extend(get_maximal_cliques(
-            parsed,
-            cr,
-            p.intersection(&nv).copied().collect(),
-            x.intersection(&nv).copied().collect(),
-        ));
+    fn is_empty(&self) -> bool {
+        self.words.iter().all(|w| *w == 0)
+    }
 
-        x.insert(v);
+    fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
     }
 
-    r.sort_by_key(|i| i.len());
+    fn intersection(&self, other: &Self) -> Self {
+        Self {
+            words: iter::zip(&self.words, &other.words)
+                .map(|(a, b)| a & b)
+                .collect(),
+        }
+    }
+
+    fn difference(&self, other: &Self) -> Self {
+        Self {
+            words: iter::zip(&self.words, &other.words)
+                .map(|(a, b)| a & !b)
+                .collect(),
+        }
+    }
 
-    r
+    fn union(&self, other: &Self) -> Self {
+        Self {
+            words: iter::zip(&self.words, &other.words)
+                .map(|(a, b)| a | b)
+                .collect(),
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &w)| {
+            iter::successors((w != 0).then_some(w), |w| {
+                let w = w & (w - 1);
+                (w != 0).then_some(w)
+            })
+            .map(move |w| i * 64 + w.trailing_zeros() as usize)
+        })
+    }
 }
 
-fn part2(parsed: &Graph<&str, (), Undirected>) {
-    let mcliques = get_maximal_cliques(
-        parsed,
-        IndexSet::new(),
-        parsed.node_indices().collect::<IndexSet<_>>(),
-        IndexSet::new(),
-    );
+// Bron–Kerbosch with Tomita pivoting over bitset neighbourhoods; a branch is
+// cut once `r` plus every remaining candidate cannot reach the best size, but
+// branches that could tie are kept so every maximum clique is reported
+fn maximum_cliques(graph: &Graph<&str, (), Undirected>) -> Vec<Vec<NodeIndex>> {
+    fn expand(
+        adj: &[BitSet],
+        r: &mut Vec<usize>,
+        mut p: BitSet,
+        mut x: BitSet,
+        best: &mut Vec<Vec<usize>>,
+    ) {
+        let best_len = best.first().map_or(0, Vec::len);
+
+        if p.is_empty() {
+            if x.is_empty() {
+                if r.len() > best_len {
+                    best.clear();
+                }
+                if r.len() >= best_len {
+                    best.push(r.clone());
+                }
+            }
+            return;
+        }
 
-    println!(
-        "part2: {:?}",
-        mcliques
-            .last()
-            .unwrap()
+        let Some(u) = p
+            .union(&x)
             .iter()
-            .map(|&i| parsed[i])
-            .sorted()
-            .join(",")
+            .max_by_key(|&u| p.intersection(&adj[u]).len())
+        else {
+            return;
+        };
+
+        for v in p.difference(&adj[u]).iter().collect::<Vec<_>>() {
+            let best_len = best.first().map_or(0, Vec::len);
+            if r.len() + p.len() < best_len {
+                return;
+            }
+
+            r.push(v);
+            expand(
+                adj,
+                r,
+                p.intersection(&adj[v]),
+                x.intersection(&adj[v]),
+                best,
+            );
+            r.pop();
+
+            p.remove(v);
+            x.insert(v);
+        }
+    }
+
+    let n = graph.node_count();
+    let adj = graph
+        .node_indices()
+        .map(|v| {
+            let mut set = BitSet::new(n);
+            for u in graph.neighbors(v) {
+                set.insert(u.index());
+            }
+            set
+        })
+        .collect::<Vec<_>>();
+
+    let mut best = Vec::new();
+    expand(
+        &adj,
+        &mut Vec::new(),
+        BitSet::full(n),
+        BitSet::new(n),
+        &mut best,
+    );
+
+    best.into_iter()
+        .map(|clique| clique.into_iter().map(NodeIndex::new).collect())
+        .collect()
+}
+
+fn maximum_clique(graph: &Graph<&str, (), Undirected>) -> Option<Vec<NodeIndex>> {
+    maximum_cliques(graph).into_iter().next()
+}
+
+fn password(graph: &Graph<&str, (), Undirected>, clique: &[NodeIndex]) -> String {
+    clique.iter().map(|&i| graph[i]).sorted().join(",")
+}
+
+fn part2(parsed: &Graph<&str, (), Undirected>) {
+    let cliques = maximum_cliques(parsed);
+    for clique in &cliques {
+        println!("part2: {:?}", password(parsed, clique));
+    }
+    if cliques.len() > 1 {
+        println!("({} maximum cliques tie)", cliques.len());
+    }
+}
+
+#[derive(Debug, Default)]
+struct Highlights {
+    clique_nodes: HashSet<NodeIndex>,
+    triangle_nodes: HashSet<NodeIndex>,
+    triangle_edges: HashSet<(NodeIndex, NodeIndex)>,
+}
+
+impl Highlights {
+    fn new(graph: &Graph<&str, (), Undirected>) -> Self {
+        let mut highlights = Self {
+            clique_nodes: maximum_clique(graph)
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+
+        for clique in k_cliques(graph, 3, |nodes| nodes.iter().any(|n| n.starts_with('t'))) {
+            for (&a, &b) in clique.iter().tuple_combinations() {
+                highlights.triangle_edges.insert((a.min(b), a.max(b)));
+            }
+            highlights.triangle_nodes.extend(clique);
+        }
+
+        highlights
+    }
+
+    fn clique_edge(&self, a: NodeIndex, b: NodeIndex) -> bool {
+        self.clique_nodes.contains(&a) && self.clique_nodes.contains(&b)
+    }
+
+    fn triangle_edge(&self, a: NodeIndex, b: NodeIndex) -> bool {
+        self.triangle_edges.contains(&(a.min(b), a.max(b)))
+    }
+}
+
+// the maximum clique is drawn in red, the `t` triangles in blue
+fn to_dot(graph: &Graph<&str, (), Undirected>, highlights: &Highlights) -> String {
+    let edge_attrs = |_, e: petgraph::graph::EdgeReference<()>| {
+        let (a, b) = (e.source(), e.target());
+        if highlights.clique_edge(a, b) {
+            "color=red, penwidth=2".to_string()
+        } else if highlights.triangle_edge(a, b) {
+            "color=blue".to_string()
+        } else {
+            "color=gray".to_string()
+        }
+    };
+    // labels are set here since `&str` weights would otherwise print quoted
+    let node_attrs = |_, (n, name): (NodeIndex, &&str)| {
+        let style = if highlights.clique_nodes.contains(&n) {
+            ", style=filled, fillcolor=red"
+        } else if highlights.triangle_nodes.contains(&n) {
+            ", style=filled, fillcolor=lightblue"
+        } else {
+            ""
+        };
+        format!("label=\"{}\"{}", name, style)
+    };
+
+    format!(
+        "{:?}",
+        Dot::with_attr_getters(
+            graph,
+            &[DotConfig::EdgeNoLabel, DotConfig::NodeNoLabel],
+            &edge_attrs,
+            &node_attrs
+        )
+    )
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn to_graphml(graph: &Graph<&str, (), Undirected>, highlights: &Highlights) -> String {
+    let mut out = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+  <key id="name" for="node" attr.name="name" attr.type="string"/>
+  <key id="clique" for="all" attr.name="in_max_clique" attr.type="boolean"/>
+  <key id="triangle" for="all" attr.name="in_t_triangle" attr.type="boolean"/>
+  <graph id="lan" edgedefault="undirected">
+"#,
     );
+
+    for n in graph.node_indices() {
+        out.push_str(&format!(
+            "    <node id=\"n{}\"><data key=\"name\">{}</data><data key=\"clique\">{}</data><data key=\"triangle\">{}</data></node>\n",
+            n.index(),
+            xml_escape(graph[n]),
+            highlights.clique_nodes.contains(&n),
+            highlights.triangle_nodes.contains(&n),
+        ));
+    }
+
+    for e in graph.edge_references() {
+        let (a, b) = (e.source(), e.target());
+        out.push_str(&format!(
+            "    <edge id=\"e{}\" source=\"n{}\" target=\"n{}\"><data key=\"clique\">{}</data><data key=\"triangle\">{}</data></edge>\n",
+            e.id().index(),
+            a.index(),
+            b.index(),
+            highlights.clique_edge(a, b),
+            highlights.triangle_edge(a, b),
+        ));
+    }
+
+    out.push_str("  </graph>\n</graphml>\n");
+    out
+}
+
+fn export(graph: &Graph<&str, (), Undirected>, dir: &Path) -> anyhow::Result<()> {
+    let highlights = Highlights::new(graph);
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("day23.dot"), to_dot(graph, &highlights))?;
+    fs::write(dir.join("day23.graphml"), to_graphml(graph, &highlights))?;
+    println!("wrote day23.dot and day23.graphml to {}", dir.display());
+    Ok(())
 }
 
 fn main() -> anyhow::Result<()> {
     let input = include_str!("../../inputs/day23.input");
     let (_, parsed) = parse(input)?;
 
+    // `cargo run --bin day23 -- export [dir]` writes the graph for graphviz/yEd
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("export") {
+        let dir = args.next().unwrap_or_else(|| ".".to_string());
+        return export(&parsed, Path::new(&dir));
+    }
+
     part1(&parsed);
     part2(&parsed);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn starts_with_t(nodes: &[&str]) -> bool {
+        nodes.iter().any(|n| n.starts_with('t'))
+    }
+
+    #[test]
+    fn sample_t_triangles() {
+        let (_, graph) = parse(include_str!("../../inputs/day23.sample")).unwrap();
+        let triangles = k_cliques(&graph, 3, starts_with_t)
+            .iter()
+            .map(|clique| clique.iter().map(|&n| graph[n]).join(","))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            triangles,
+            [
+                "co,de,ta", "co,ka,ta", "de,ka,ta", "qp,td,wh", "tb,vc,wq", "tc,td,wh", "td,wh,yn"
+            ]
+        );
+        assert_eq!(count_k_cliques(&graph, 3, starts_with_t), 7);
+    }
+
+    #[test]
+    fn count_matches_enumeration() {
+        let (_, graph) = parse(include_str!("../../inputs/day23.input")).unwrap();
+        for k in 1..=4 {
+            assert_eq!(
+                count_k_cliques(&graph, k, |_| true),
+                k_cliques(&graph, k, |_| true).len()
+            );
+        }
+        assert_eq!(
+            count_k_cliques(&graph, 3, starts_with_t),
+            k_cliques(&graph, 3, starts_with_t).len()
+        );
+    }
+
+    #[test]
+    fn filters_see_sorted_names() {
+        let (_, graph) = parse(include_str!("../../inputs/day23.sample")).unwrap();
+        let sorted = |nodes: &[&str]| nodes.is_sorted();
+        assert_eq!(
+            count_k_cliques(&graph, 3, sorted),
+            count_k_cliques(&graph, 3, |_| true)
+        );
+        assert_eq!(
+            k_cliques(&graph, 3, sorted).len(),
+            k_cliques(&graph, 3, |_| true).len()
+        );
+    }
+
+    fn complete_graph_edges(names: &[&str]) -> String {
+        names
+            .iter()
+            .tuple_combinations()
+            .map(|(a, b)| format!("{}-{}\n", a, b))
+            .collect()
+    }
+
+    fn passwords(graph: &Graph<&str, (), Undirected>) -> Vec<String> {
+        maximum_cliques(graph)
+            .iter()
+            .map(|clique| password(graph, clique))
+            .sorted()
+            .collect()
+    }
+
+    #[test]
+    fn sample_password() {
+        let (_, graph) = parse(include_str!("../../inputs/day23.sample")).unwrap();
+        assert_eq!(passwords(&graph), ["co,de,ka,ta"]);
+    }
+
+    #[test]
+    fn returns_every_tied_maximum_clique() {
+        let input = complete_graph_edges(&["aa", "ab", "ac", "ad"])
+            + &complete_graph_edges(&["ba", "bb", "bc", "bd"]);
+        let (_, graph) = parse(&input).unwrap();
+        assert_eq!(passwords(&graph), ["aa,ab,ac,ad", "ba,bb,bc,bd"]);
+    }
+
+    #[test]
+    fn pruning_keeps_only_the_largest() {
+        // a K5 next to a K4 sharing one node, plus a pendant triangle
+        let input = complete_graph_edges(&["aa", "ab", "ac", "ad", "ae"])
+            + &complete_graph_edges(&["ae", "ba", "bb", "bc"])
+            + &complete_graph_edges(&["bc", "ca", "cb"]);
+        let (_, graph) = parse(&input).unwrap();
+        assert_eq!(passwords(&graph), ["aa,ab,ac,ad,ae"]);
+    }
+
+    #[test]
+    fn maximum_clique_matches_k_clique_search() {
+        let (_, graph) = parse(include_str!("../../inputs/day23.input")).unwrap();
+        let size = maximum_clique(&graph).unwrap().len();
+        assert_eq!(count_k_cliques(&graph, size + 1, |_| true), 0);
+        assert_eq!(
+            k_cliques(&graph, size, |_| true).len(),
+            maximum_cliques(&graph).len()
+        );
+    }
+}